@@ -314,8 +314,10 @@ impl Board {
         self.cached_in_check_val = None;
     }
 
+    /// Passes the turn to the opponent without moving a piece. The side to move must not be in
+    /// check.
     pub fn make_null_move(&mut self, zobrist: &Zobrist) {
-        self.white_to_move = !self.white_to_move;
+        self.swap_side_to_move();
         self.plycount += 1;
         
         let mut new_zobrist_key = self.current_state.zobrist_key;
@@ -336,12 +338,27 @@ impl Board {
     }
 
     pub fn unmake_null_move(&mut self) {
-        self.white_to_move = !self.white_to_move;
+        self.swap_side_to_move();
         self.plycount -= 1;
         self.game_state_history.pop();
         self.current_state = self.game_state_history[self.game_state_history.len() - 1];
         self.update_slider_bitboards();
-        self.cached_in_check_val = Some(false);
+        self.cached_in_check_val = None;
+    }
+
+    fn swap_side_to_move(&mut self) {
+        self.white_to_move = !self.white_to_move;
+        self.move_color = if self.white_to_move { Piece::WHITE } else { Piece::BLACK };
+        self.opponent_color = if self.white_to_move { Piece::BLACK } else { Piece::WHITE };
+        self.move_color_idx = 1 - self.move_color_idx;
+        self.opponent_color_idx = 1 - self.opponent_color_idx;
+    }
+
+    /// Number of knights, bishops, rooks and queens the side to move has.
+    pub fn friendly_non_pawn_pieces(&self) -> u32 {
+        let pawns = self.piece_bitboards[Piece::new(Piece::PAWN | self.move_color)];
+        let king = self.piece_bitboards[Piece::new(Piece::KING | self.move_color)];
+        (self.color_bitboards[self.move_color_idx].0 & !(pawns.0 | king.0)).count_ones()
     }
     
     pub fn load_position(fen_str: Option<String>, zobrist: &mut Zobrist) -> Self {
@@ -436,6 +453,57 @@ impl Board {
         false
     }
 
+    /// Whether the legal move `m` would put the opponent in check, without making it.
+    pub fn gives_check(&self, m: Move) -> bool {
+        let (start, target) = (m.start(), m.target());
+        let king_sqr = self.king_square[self.opponent_color_idx];
+        let moved_ptype = if m.is_promotion() { m.promotion_ptype() } else { self.square[start].piece_type() };
+
+        // Where the pieces that could give check will be once the move is made
+        let mut blockers = self.all_pieces_bitboard;
+        let mut orthogonal = self.friendly_orthogonal_sliders;
+        let mut diagonal = self.friendly_diagonal_sliders;
+        blockers.clear_square(start.square());
+        blockers.set_square(target.square());
+        orthogonal.clear_square(start.square());
+        diagonal.clear_square(start.square());
+        if moved_ptype == Piece::ROOK || moved_ptype == Piece::QUEEN {
+            orthogonal.set_square(target.square());
+        }
+        if moved_ptype == Piece::BISHOP || moved_ptype == Piece::QUEEN {
+            diagonal.set_square(target.square());
+        }
+
+        match m.move_flag() {
+            Move::EN_PASSANT_CAPTURE => {
+                blockers.clear_square((target + if self.white_to_move { -8 } else { 8 }).square());
+            },
+            Move::CASTLING => {
+                let kingside = target == Coord::G1 || target == Coord::G8;
+                let rook_from = if kingside { target + 1 } else { target - 2 };
+                let rook_to = if kingside { target - 1 } else { target + 1 };
+                blockers.clear_square(rook_from.square());
+                blockers.set_square(rook_to.square());
+                orthogonal.clear_square(rook_from.square());
+                orthogonal.set_square(rook_to.square());
+            },
+            _ => (),
+        }
+
+        let direct = match moved_ptype {
+            Piece::PAWN => {
+                let attacks = if self.white_to_move { Precomputed::white_pawn_attacks(target) } else { Precomputed::black_pawn_attacks(target) };
+                attacks.contains_square(king_sqr.square())
+            },
+            Piece::KNIGHT => Precomputed::knight_moves(target).contains_square(king_sqr.square()),
+            _ => false,
+        };
+
+        direct
+            || (Magics::rook_attacks(king_sqr, blockers) & orthogonal).0 != 0
+            || (Magics::bishop_attacks(king_sqr, blockers) & diagonal).0 != 0
+    }
+
     pub fn move_piece(&mut self, piece: Piece, start: Coord, target: Coord) {
         self.piece_bitboards[piece].toggle_squares(start.square(), target.square());
         self.color_bitboards[piece.color_index()].toggle_squares(start.square(), target.square());
@@ -523,3 +591,56 @@ impl std::fmt::Debug for Board {
         write!(f, "{}", s)
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::{piece::Piece, zobrist::Zobrist}, move_gen::{magics, move_generator::MoveGenerator}, precomp};
+    use super::Board;
+
+    #[test]
+    fn test_null_move() {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let mut board = Board::load_position(Some(String::from("4k3/8/8/8/3r4/8/8/2B1K3 w - - 0 1")), &mut zobrist);
+        let before = board.clone();
+
+        board.make_null_move(&zobrist);
+        assert!(!board.white_to_move);
+        assert_eq!(board.move_color, Piece::BLACK);
+        assert_eq!(board.move_color_idx, Board::BLACK_INDEX);
+        assert_eq!(board.friendly_non_pawn_pieces(), 1);
+        assert_ne!(board.current_state.zobrist_key, before.current_state.zobrist_key);
+
+        board.unmake_null_move();
+        assert!(board == before);
+    }
+
+    #[test]
+    fn test_gives_check() {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let mut movegen = MoveGenerator::default();
+
+        // Direct and discovered checks, checks by castling, en passant and promotion
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+            "8/8/8/K2pP2r/8/8/8/7k w - d6 0 1",
+            "1k6/8/8/8/8/1B6/8/1R2K3 w - - 0 1",
+            "3k4/1P6/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/2b5/8/8/5p2/3N3K b - - 0 1",
+        ];
+        for fen in fens {
+            let mut board = Board::load_position(Some(String::from(fen)), &mut zobrist);
+            for m in movegen.generate_moves(&board, false) {
+                let gives_check = board.gives_check(m);
+                board.make_move(m, true, &zobrist);
+                assert_eq!(gives_check, board.in_check(), "{} in {}", m.name(), fen);
+                board.unmake_move(m, true);
+            }
+        }
+    }
+}
@@ -4,6 +4,7 @@ use super::piece::*;
 
 const SEED: u64 = 29426028;
 
+#[derive(Clone)]
 pub struct Zobrist {
     pub pieces_array: [[u64; (Piece::MAX_PIECE_INDEX + 1) as usize]; 64], // index [square][piece]
     pub castling_rights: [u64; 16],
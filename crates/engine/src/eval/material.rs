@@ -12,6 +12,9 @@ impl<'a> Evaluation<'a> {
             + Self::PIECE_VALUE_BONUS_MG[4] * self.queen_count::<W, B>()
     }
 
+    /// Endgame value of a pawn, which is what one centipawn is measured against.
+    pub const PAWN_VALUE_EG: i32 = Self::PIECE_VALUE_BONUS_EG[0];
+
     const PIECE_VALUE_BONUS_MG: [i32; 5] = [124, 781, 825, 1276, 2538];
     const PIECE_VALUE_BONUS_EG: [i32; 5] = [206, 854, 915, 1380, 2682];
 
@@ -1,6 +1,6 @@
 use std::collections::VecDeque;
 
-use crate::{board::{coord::Coord, moves::Move, piece::Piece, zobrist::Zobrist, Board}, move_gen::{magics::{self, Magics}, move_generator::MoveGenerator}, precomp::{self, Precomputed}, prelude::BitBoard, result::GameResult, search::{options::SearchOptions, Searcher}};
+use crate::{board::{coord::Coord, moves::Move, piece::Piece, zobrist::Zobrist, Board}, move_gen::{magics::{self, Magics}, move_generator::MoveGenerator}, precomp::{self, Precomputed}, prelude::BitBoard, result::GameResult, search::{handle::SearchHandle, options::SearchOptions, repetition::RepetitionTable}};
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum PlayerType {
@@ -8,32 +8,35 @@ pub enum PlayerType {
     Computer,
 }
 
-pub struct Game<'a> {
+pub struct Game {
     pub board: Board,
     pub zobrist: Zobrist,
     pub movegen: MoveGenerator,
 
     pub white: PlayerType,
     pub black: PlayerType,
-    pub searcher: Searcher<'a>,
+    pub searcher: SearchHandle,
     pub search_opts: SearchOptions,
     pub player_to_move: PlayerType,
+    /// Think on the human's time about the reply the computer expects
+    pub ponder: bool,
+    ponder_move: Option<Move>,
 }
 
-impl<'a> Game<'a> {
+impl Game {
     pub fn new(start_fen: Option<String>, search_opts: SearchOptions, white: PlayerType, black: PlayerType) -> Self {
         precomp::initialize();
         magics::initialize();
         let mut zobrist = Zobrist::new();
         let mut board = Board::load_position(start_fen, &mut zobrist);
         let mut movegen = MoveGenerator::default();
-        let mut searcher = Searcher::new();
+        let mut searcher = SearchHandle::new();
 
         movegen.generate_moves(&board, false);
         let player_to_move = if board.white_to_move { white } else { black };
 
         if player_to_move == PlayerType::Computer {
-            searcher.begin_search(search_opts, &mut board, &zobrist, &mut movegen);
+            searcher.begin_search(search_opts.clone(), &board, &zobrist);
         }
 
         Self {
@@ -45,6 +48,8 @@ impl<'a> Game<'a> {
             searcher,
             search_opts,
             player_to_move,
+            ponder: false,
+            ponder_move: None,
         }
     }
 
@@ -56,6 +61,13 @@ impl<'a> Game<'a> {
     pub fn make_move_post(&mut self) -> GameResult {
         let result = self.get_game_result();
 
+        // The ponder search carries on if the expected move was played, otherwise it is useless
+        let ponderhit = !result.is_terminal()
+            && self.ponder_move.take().is_some_and(|m| self.board.move_log.last() == Some(&m));
+        if !ponderhit && self.searcher.is_pondering() {
+            self.searcher.abort();
+        }
+
         if result.is_terminal() {
             return result;
         }
@@ -64,9 +76,12 @@ impl<'a> Game<'a> {
         match self.player_to_move {
             PlayerType::Human => {
 
+            },
+            PlayerType::Computer if ponderhit => {
+                self.searcher.ponderhit();
             },
             PlayerType::Computer => {
-                self.searcher.begin_search(self.search_opts, &mut self.board, &self.zobrist, &mut self.movegen);
+                self.searcher.begin_search(self.search_opts.clone(), &self.board, &self.zobrist);
             },
         }
 
@@ -74,7 +89,31 @@ impl<'a> Game<'a> {
     }
 
     pub fn try_make_computer_move(&mut self) -> Option<GameResult> {
-        self.searcher.best_move().map(|m| self.make_move(m))
+        let m = self.searcher.best_move()?;
+        let reply = self.searcher.ponder_move();
+        let result = self.make_move(m);
+
+        if self.ponder && !result.is_terminal() && self.player_to_move == PlayerType::Human {
+            if let Some(reply) = reply.filter(|r| self.movegen.moves.contains(r)) {
+                self.begin_pondering(reply);
+            }
+        }
+
+        Some(result)
+    }
+
+    /// Searches the position after `reply` while the human is thinking. The search only starts
+    /// using the computer's time once the human actually plays `reply`.
+    fn begin_pondering(&mut self, reply: Move) {
+        let mut board = self.board.clone();
+        board.make_move(reply, false, &self.zobrist);
+        if MoveGenerator::default().generate_moves(&board, false).is_empty() {
+            return;
+        }
+
+        let opts = SearchOptions { ponder: true, ..self.search_opts.clone() };
+        self.searcher.begin_search(opts, &board, &self.zobrist);
+        self.ponder_move = Some(reply);
     }
 
     pub fn get_game_result(&mut self) -> GameResult {
@@ -92,8 +131,7 @@ impl<'a> Game<'a> {
             return GameResult::FiftyMoveRule;
         }
 
-        let rep_count = self.board.repeat_position_history.iter().filter(|x| **x == self.board.current_state.zobrist_key).count();
-        if rep_count == 3 {
+        if RepetitionTable::new(&self.board).occurrences() >= 3 {
             return GameResult::Repetition;
         }
 
@@ -138,10 +176,14 @@ impl<'a> Game<'a> {
     pub fn undo_move(&mut self) {
         let Some(m) = self.board.move_log.last() else { return };
         self.board.unmake_move(*m, false);
+
+        if self.ponder_move.take().is_some() {
+            self.searcher.abort();
+        }
     }
 
     pub fn valid_human_moves(&mut self, sqr: Coord) -> Vec<Move> {
-        if !self.searcher.in_search {
+        if !self.searcher.in_search() || self.searcher.is_pondering() {
             self.movegen.moves.iter().cloned().filter(|m| m.start() == sqr).collect()
         } else {
             Vec::new()
@@ -1,3 +1,4 @@
 pub mod move_generator;
+pub mod move_list;
 pub mod magics;
 pub mod prng;
@@ -1,7 +1,7 @@
 use crate::board::{coord::Coord, moves::Move, Board, piece::Piece};
 
 use crate::bitboard::bb::BitBoard;
-use super::magics::Magics;
+use super::{magics::Magics, move_list::MoveList};
 use crate::precomp::Precomputed;
 
 
@@ -16,7 +16,7 @@ pub enum PromotionMode {
 
 #[derive(Clone)]
 pub struct MoveGenerator {
-    pub moves: Vec<Move>,
+    pub moves: MoveList,
     pub promotions_to_gen: PromotionMode,
     
     pub white_to_move: bool,
@@ -39,6 +39,7 @@ pub struct MoveGenerator {
     pub enemy_sliding_attack_map: BitBoard,
 
     gen_quiet_moves: bool,
+    gen_capture_moves: bool,
 
     enemy_pieces: BitBoard,
     friendly_pieces: BitBoard,
@@ -50,8 +51,28 @@ pub struct MoveGenerator {
 
 impl MoveGenerator {
     pub fn generate_moves(&mut self, board: &Board, captures_only: bool) -> Vec<Move> {
+        self.generate(board, !captures_only, true);
+        self.moves.to_vec()
+    }
+
+    /// Generates captures and queen promotions into `self.moves`. These are the same moves as
+    /// `generate_moves(board, true)`.
+    pub fn generate_captures(&mut self, board: &Board) -> &MoveList {
+        self.generate(board, false, true);
+        &self.moves
+    }
+
+    /// Generates the moves left out by [`Self::generate_captures`] into `self.moves`: quiet moves
+    /// and underpromotions.
+    pub fn generate_quiets(&mut self, board: &Board) -> &MoveList {
+        self.generate(board, true, false);
+        &self.moves
+    }
+
+    fn generate(&mut self, board: &Board, quiets: bool, captures: bool) {
         self.moves.clear();
-        self.gen_quiet_moves = !captures_only;
+        self.gen_quiet_moves = quiets;
+        self.gen_capture_moves = captures;
 
         self.init(board);
         self.gen_king_moves(board);
@@ -61,14 +82,145 @@ impl MoveGenerator {
             self.gen_knight_moves(board);
             self.gen_pawn_moves(board);
         }
-
-        self.moves.clone()
     }
 
     pub fn in_check(&self) -> bool {
         self.in_check
     }
-    
+
+    /// Whether `m` is one of the moves [`Self::generate_moves`] would generate for `board`,
+    /// checked directly on the board without generating anything. Meant for moves that may come
+    /// from another position, such as the move stored in the transposition table.
+    pub fn is_legal(&self, board: &Board, m: Move) -> bool {
+        if m == Move::NULL {
+            return false;
+        }
+
+        let (start, target) = (m.start(), m.target());
+        let piece = board.square[start];
+        let captured = board.square[target];
+        if piece.piece_type() == Piece::NONE || !piece.is_color(board.move_color) {
+            return false;
+        }
+        if captured.piece_type() != Piece::NONE && (captured.is_color(board.move_color) || captured.piece_type() == Piece::KING) {
+            return false;
+        }
+
+        let white = board.white_to_move;
+        let flag = m.move_flag();
+        let is_capture = captured.piece_type() != Piece::NONE;
+        let mut removed = if is_capture { target.to_bitboard() } else { BitBoard(0) };
+
+        let pseudo_legal = match piece.piece_type() {
+            Piece::PAWN => {
+                let forward = if white { 8 } else { -8 };
+                let attacks = if white { Precomputed::white_pawn_attacks(start) } else { Precomputed::black_pawn_attacks(start) };
+                let promotes = target.rank() == if white { 7 } else { 0 };
+                let one_forward = target.square() == start.square() + forward && !is_capture;
+                let diagonal = attacks.contains_square(target.square());
+
+                match flag {
+                    Move::NORMAL => !promotes && (one_forward || (diagonal && is_capture)),
+                    Move::PAWN_TWO_FORWARD => {
+                        start.rank() == if white { 1 } else { 6 }
+                        && target.square() == start.square() + 2 * forward
+                        && board.square[start + forward].piece_type() == Piece::NONE
+                        && !is_capture
+                    },
+                    Move::EN_PASSANT_CAPTURE => {
+                        let ep_file = board.current_state.en_passant_file - 1;
+                        removed = (target - forward).to_bitboard();
+                        ep_file >= 0
+                        && target.file() == ep_file
+                        && target.rank() == if white { 5 } else { 2 }
+                        && diagonal
+                        && !is_capture
+                    },
+                    Move::QUEEN_PROMOTION | Move::KNIGHT_PROMOTION | Move::ROOK_PROMOTION | Move::BISHOP_PROMOTION => {
+                        let generated = match self.promotions_to_gen {
+                            PromotionMode::All => true,
+                            PromotionMode::QueenAndKnight => flag == Move::QUEEN_PROMOTION || flag == Move::KNIGHT_PROMOTION,
+                            PromotionMode::QueenOnly => flag == Move::QUEEN_PROMOTION,
+                        };
+                        generated && promotes && (one_forward || (diagonal && is_capture))
+                    },
+                    _ => false,
+                }
+            },
+            ptype if flag != Move::NORMAL => ptype == Piece::KING && flag == Move::CASTLING && Self::can_castle(board, target),
+            Piece::KNIGHT => Precomputed::knight_moves(start).contains_square(target.square()),
+            Piece::BISHOP => Magics::bishop_attacks(start, board.all_pieces_bitboard).contains_square(target.square()),
+            Piece::ROOK => Magics::rook_attacks(start, board.all_pieces_bitboard).contains_square(target.square()),
+            Piece::QUEEN => (Magics::rook_attacks(start, board.all_pieces_bitboard) | Magics::bishop_attacks(start, board.all_pieces_bitboard)).contains_square(target.square()),
+            Piece::KING => Precomputed::king_moves(start).contains_square(target.square()),
+            _ => false,
+        };
+        if !pseudo_legal {
+            return false;
+        }
+        if flag == Move::CASTLING {
+            return true;
+        }
+
+        // The move is legal if it doesn't leave the king attacked
+        let king_sqr = if piece.piece_type() == Piece::KING { target } else { board.king_square[board.move_color_idx] };
+        let mut blockers = board.all_pieces_bitboard & !removed;
+        blockers.clear_square(start.square());
+        blockers.set_square(target.square());
+        !Self::attacked(board, king_sqr, blockers, removed)
+    }
+
+    /// Whether the king can castle to `target`, which is done when it isn't in check and doesn't
+    /// pass through or land on an attacked square.
+    fn can_castle(board: &Board, target: Coord) -> bool {
+        let white = board.white_to_move;
+        let king_sqr = board.king_square[board.move_color_idx];
+        let (kingside_target, queenside_target) = if white { (Coord::G1, Coord::C1) } else { (Coord::G8, Coord::C8) };
+
+        let (path, empty) = if target == kingside_target && board.current_state.has_kingside_castle_right(white) {
+            let mask = if white { Precomputed::WHITE_KINGSIDE_MASK } else { Precomputed::BLACK_KINGSIDE_MASK };
+            (mask, mask)
+        } else if target == queenside_target && board.current_state.has_queenside_castle_right(white) {
+            if white {
+                (Precomputed::WHITE_QUEENSIDE_MASK_2, Precomputed::WHITE_QUEENSIDE_MASK)
+            } else {
+                (Precomputed::BLACK_QUEENSIDE_MASK_2, Precomputed::BLACK_QUEENSIDE_MASK)
+            }
+        } else {
+            return false;
+        };
+
+        let blockers = board.all_pieces_bitboard;
+        if (empty & blockers).0 != 0 || Self::attacked(board, king_sqr, blockers, BitBoard(0)) {
+            return false;
+        }
+
+        let mut path = path;
+        while path.0 != 0 {
+            if Self::attacked(board, Coord::from_idx(path.pop_lsb() as i8), blockers, BitBoard(0)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Whether `sqr` is attacked by the opponent of the side to move, with `blockers` as the
+    /// occupied squares and the opponent's pieces on `removed` taken off the board.
+    fn attacked(board: &Board, sqr: Coord, blockers: BitBoard, removed: BitBoard) -> bool {
+        let enemy = board.opponent_color;
+        let remaining = !removed;
+        let knights = board.piece_bitboards[Piece::new(Piece::KNIGHT | enemy)] & remaining;
+        let pawns = board.piece_bitboards[Piece::new(Piece::PAWN | enemy)] & remaining;
+        let king = board.piece_bitboards[Piece::new(Piece::KING | enemy)];
+        let pawn_attackers = if board.white_to_move { Precomputed::white_pawn_attacks(sqr) } else { Precomputed::black_pawn_attacks(sqr) };
+
+        (Magics::rook_attacks(sqr, blockers) & board.enemy_orthogonal_sliders & remaining).0 != 0
+            || (Magics::bishop_attacks(sqr, blockers) & board.enemy_diagonal_sliders & remaining).0 != 0
+            || (Precomputed::knight_moves(sqr) & knights).0 != 0
+            || (pawn_attackers & pawns).0 != 0
+            || (Precomputed::king_moves(sqr) & king).0 != 0
+    }
+
     fn init(&mut self, board: &Board) {
         self.in_check = false;
         self.in_double_check = false;
@@ -87,7 +239,11 @@ impl MoveGenerator {
         self.all_pieces = board.all_pieces_bitboard;
         self.empty_sqrs = !self.all_pieces;
         self.empty_or_enemy_sqrs = self.empty_sqrs | self.enemy_pieces;
-        self.move_type_mask = if self.gen_quiet_moves { BitBoard::ALL } else { self.enemy_pieces };
+        self.move_type_mask = match (self.gen_quiet_moves, self.gen_capture_moves) {
+            (true, true) => BitBoard::ALL,
+            (true, false) => self.empty_sqrs,
+            _ => self.enemy_pieces,
+        };
 
         self.calc_attack_data(board);
     }
@@ -319,6 +475,11 @@ impl MoveGenerator {
             }
         }
 
+        if !self.gen_capture_moves {
+            capture_a = BitBoard(0);
+            capture_b = BitBoard(0);
+        }
+
         while capture_a.0 != 0 {
             let target_sqr = capture_a.pop_lsb() as i8;
             let start_sqr = target_sqr - push_dir * 7;
@@ -364,7 +525,7 @@ impl MoveGenerator {
             }
         }
 
-        if board.current_state.en_passant_file > 0 {
+        if self.gen_capture_moves && board.current_state.en_passant_file > 0 {
             let ep_file_idx = board.current_state.en_passant_file - 1;
             let ep_rank_idx = if self.white_to_move { 5 } else { 2 };
             let target_sqr = ep_rank_idx * 8 + ep_file_idx;
@@ -385,7 +546,9 @@ impl MoveGenerator {
     }
 
     fn gen_proms(&mut self, start_sqr: i8, target_sqr: i8) {
-        self.moves.push(Move::from_start_end_flagged(start_sqr, target_sqr, Move::QUEEN_PROMOTION));
+        if self.gen_capture_moves {
+            self.moves.push(Move::from_start_end_flagged(start_sqr, target_sqr, Move::QUEEN_PROMOTION));
+        }
         if self.gen_quiet_moves {
             if self.promotions_to_gen == PromotionMode::All {
                 self.moves.push(Move::from_start_end_flagged(start_sqr, target_sqr, Move::KNIGHT_PROMOTION));
@@ -412,7 +575,7 @@ impl MoveGenerator {
 impl Default for MoveGenerator {
     fn default() -> Self {
         MoveGenerator {
-            moves: Vec::new(),
+            moves: MoveList::new(),
             promotions_to_gen: PromotionMode::All,
             white_to_move: true,
             friendly_color: Piece::WHITE,
@@ -430,6 +593,7 @@ impl Default for MoveGenerator {
             enemy_pawn_attack_map: BitBoard(0),
             enemy_sliding_attack_map: BitBoard(0),
             gen_quiet_moves: true,
+            gen_capture_moves: true,
             enemy_pieces: BitBoard(0),
             friendly_pieces: BitBoard(0),
             all_pieces: BitBoard(0),
@@ -439,3 +603,62 @@ impl Default for MoveGenerator {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::{moves::Move, zobrist::Zobrist, Board}, move_gen::magics, precomp};
+    use super::MoveGenerator;
+
+    #[test]
+    fn test_captures_and_quiets_partition_moves() {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let mut movegen = MoveGenerator::default();
+
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r1b1k2r/pPpp1ppp/2n2n2/4p3/1b2P3/2N2N2/P1PP1PPP/R1BQKB1R w KQkq - 0 1",
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+        ] {
+            let board = Board::load_position(Some(fen.to_string()), &mut zobrist);
+            let mut all = movegen.generate_moves(&board, false);
+            let captures = movegen.generate_captures(&board).to_vec();
+            assert_eq!(captures, movegen.generate_moves(&board, true));
+
+            let mut split = captures;
+            split.extend(movegen.generate_quiets(&board).iter());
+            all.sort_unstable_by_key(|m| m.value());
+            split.sort_unstable_by_key(|m| m.value());
+            assert_eq!(all, split);
+        }
+    }
+
+    #[test]
+    fn test_is_legal() {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let mut movegen = MoveGenerator::default();
+
+        // Castling through check, pins, en passant, promotions and a king in check
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+            "r1b1k2r/pPpp1ppp/2n2n2/4p3/1b2P3/2N2N2/P1PP1PPP/R1BQKB1R w KQkq - 0 1",
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+            "8/8/8/K2pP2r/8/8/8/7k w - d6 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K1r1 w Qkq - 0 1",
+            "4k3/8/8/8/1b6/8/3P4/4K3 w - - 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2",
+        ] {
+            let board = Board::load_position(Some(fen.to_string()), &mut zobrist);
+            let moves = movegen.generate_moves(&board, false);
+            for value in 0..=u16::MAX {
+                let m = Move::from_value(value);
+                assert_eq!(movegen.is_legal(&board, m), moves.contains(&m), "{} in {}", m.name(), fen);
+            }
+        }
+    }
+}
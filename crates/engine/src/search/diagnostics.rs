@@ -1,9 +1,24 @@
-use super::Searcher;
+use std::time::Duration;
+
+use crate::{board::moves::Move, eval::Evaluation};
+
+use super::{pv::PrincipalVariation, Searcher};
 
 #[derive(Default, Clone, Copy)]
 pub struct SearchDiagnostics {
     pub depth_searched: u8,
+    /// Deepest ply reached in the last iteration, including quiescence search
+    pub seldepth: u8,
     pub evaluation: i32,
+    pub nodes: u64,
+    /// Time spent searching
+    pub time: Duration,
+    /// How full the transposition table is in permille
+    pub hashfull: u32,
+    /// Principal variation of the last completed iteration
+    pub pv: PrincipalVariation,
+    /// Which of the best lines this is when searching with MultiPV, counting from 1
+    pub multipv: usize,
 }
 
 impl SearchDiagnostics {
@@ -14,4 +29,60 @@ impl SearchDiagnostics {
     pub fn moves_till_mate(&self) -> i32 {
         Searcher::IMMEDIATE_MATE_SCORE - self.evaluation.abs() - 1
     }
+
+    /// Number of moves (not plies) until mate as reported over UCI. Negative if the side to move
+    /// is getting mated.
+    pub fn mate_in(&self) -> i32 {
+        let moves = (Searcher::IMMEDIATE_MATE_SCORE - self.evaluation.abs() + 1) / 2;
+        moves * self.evaluation.signum()
+    }
+
+    /// The evaluation in centipawns.
+    pub fn centipawns(&self) -> i32 {
+        self.evaluation * 100 / Evaluation::PAWN_VALUE_EG
+    }
+
+    /// Nodes searched per second.
+    pub fn nps(&self) -> u64 {
+        (self.nodes as u128 * 1000 / self.time.as_millis().max(1)) as u64
+    }
+}
+
+/// Progress sent by the main search thread while it is running.
+#[derive(Clone, Copy)]
+pub enum SearchInfo {
+    /// An iteration finished, or the search was stopped after finding a new best move
+    Iteration(SearchDiagnostics),
+    /// The search started on the `number`th root move (counting from 1)
+    CurrentMove { depth: u8, m: Move, number: usize },
+    /// A mate search proved that there is no forced mate in `moves` moves or less, using only
+    /// checks if `checks_only` is set
+    NoMate { moves: u16, checks_only: bool },
+    /// A mate search was stopped before it could prove or rule out a mate in `moves` moves
+    MateStopped { moves: u16 },
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::search::Searcher;
+    use super::SearchDiagnostics;
+
+    #[test]
+    fn test_mate_in() {
+        let mut diag = SearchDiagnostics { evaluation: Searcher::IMMEDIATE_MATE_SCORE - 1, ..Default::default() };
+        assert_eq!(diag.mate_in(), 1);
+        diag.evaluation = Searcher::IMMEDIATE_MATE_SCORE - 3;
+        assert_eq!(diag.mate_in(), 2);
+        diag.evaluation = -(Searcher::IMMEDIATE_MATE_SCORE - 2);
+        assert_eq!(diag.mate_in(), -1);
+    }
+
+    #[test]
+    fn test_nps() {
+        let diag = SearchDiagnostics { nodes: 5000, time: Duration::from_millis(500), ..Default::default() };
+        assert_eq!(diag.nps(), 10000);
+    }
 }
@@ -0,0 +1,213 @@
+use std::{sync::{atomic::{AtomicBool, AtomicU64, Ordering}, mpsc::{self, Receiver}, Arc}, thread::{self, JoinHandle}};
+
+use crate::{board::{moves::Move, zobrist::Zobrist, Board}, move_gen::move_generator::MoveGenerator};
+
+use super::{diagnostics::{SearchDiagnostics, SearchInfo}, options::SearchOptions, transpositions::TranspositionTable, Searcher};
+
+/// Runs [`Searcher`]s on background threads.
+///
+/// Each searcher is moved onto its own thread for the duration of a search and handed back once
+/// the search finishes, so nothing blocks the caller while the engine is thinking. Setting the
+/// shared stop flag with [`SearchHandle::abort`] exits the search at the next node.
+///
+/// With more than one thread, the extra threads are Lazy SMP helpers: they search the same root
+/// as the main thread and only communicate with it through the shared transposition table. The
+/// result of the search is always that of the main thread, which stops the helpers when it
+/// finishes.
+///
+/// Progress of the main thread can be followed with [`SearchHandle::poll_info`] while the search
+/// is running.
+///
+/// A search started with [`SearchOptions::ponder`] ignores its time limits and holds back its
+/// best move until [`SearchHandle::ponderhit`] is called, after which it continues as a normal
+/// search with the clock starting at the ponderhit.
+pub struct SearchHandle {
+    pub diagnostics: SearchDiagnostics,
+    transposition_table: Arc<TranspositionTable>,
+    searchers: Vec<Searcher>,
+    threads: Vec<JoinHandle<Searcher>>,
+    stop: Arc<AtomicBool>,
+    helper_nodes: Arc<AtomicU64>,
+    pondering: Arc<AtomicBool>,
+    info: Option<Receiver<SearchInfo>>,
+    best_move: Option<Move>,
+}
+
+impl SearchHandle {
+    pub fn new() -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let transposition_table = Arc::new(TranspositionTable::default());
+
+        Self {
+            diagnostics: SearchDiagnostics::default(),
+            searchers: vec![Searcher::new(stop.clone(), transposition_table.clone(), 0)],
+            transposition_table,
+            threads: Vec::new(),
+            stop,
+            helper_nodes: Arc::new(AtomicU64::new(0)),
+            pondering: Arc::new(AtomicBool::new(false)),
+            info: None,
+            best_move: None,
+        }
+    }
+
+    /// Starts searching for the best move in the position on `opts.threads` background threads and
+    /// returns immediately. Any search that is still running is stopped first.
+    ///
+    /// Assumes that that the position has valid moves (not stalemate or checkmate).
+    pub fn begin_search(&mut self, opts: SearchOptions, board: &Board, zobrist: &Zobrist) {
+        self.abort();
+        self.wait();
+
+        // Helpers make the result depend on how the threads are scheduled
+        let n_threads = if opts.deterministic { 1 } else { opts.threads.max(1) };
+        self.searchers.truncate(n_threads);
+        while self.searchers.len() < n_threads {
+            let id = self.searchers.len();
+            self.searchers.push(Searcher::new(self.stop.clone(), self.transposition_table.clone(), id));
+        }
+
+        self.best_move = None;
+        self.stop.store(false, Ordering::Relaxed);
+        self.helper_nodes.store(0, Ordering::Relaxed);
+        self.pondering.store(opts.ponder, Ordering::Relaxed);
+        self.transposition_table.new_search();
+
+        let (sender, receiver) = mpsc::channel();
+        self.info = Some(receiver);
+
+        for mut searcher in self.searchers.drain(..) {
+            searcher.helper_nodes = self.helper_nodes.clone();
+            searcher.pondering = self.pondering.clone();
+            searcher.info = if searcher.thread_id == 0 { Some(sender.clone()) } else { None };
+
+            let opts = opts.clone();
+            let mut board = board.clone();
+            let zobrist = zobrist.clone();
+            let stop = self.stop.clone();
+
+            self.threads.push(thread::spawn(move || {
+                let mut movegen = MoveGenerator::default();
+                searcher.begin_search(opts, &mut board, &zobrist, &mut movegen);
+
+                // Once the main thread is done, there's no point in the helpers continuing.
+                if searcher.thread_id == 0 {
+                    stop.store(true, Ordering::Relaxed);
+                }
+
+                searcher
+            }));
+        }
+    }
+
+    /// Whether a search is currently running.
+    pub fn in_search(&self) -> bool {
+        self.threads.first().is_some_and(|t| !t.is_finished())
+    }
+
+    /// Progress sent by the main thread since the last call, oldest first.
+    pub fn poll_info(&self) -> Vec<SearchInfo> {
+        self.info.as_ref().map_or(Vec::new(), |receiver| receiver.try_iter().collect())
+    }
+
+    /// The best move found by the last search, or `None` if a search is still running.
+    pub fn best_move(&mut self) -> Option<Move> {
+        if self.in_search() {
+            return None;
+        }
+
+        self.wait();
+        self.best_move
+    }
+
+    /// Whether the running search is a ponder search still waiting for the opponent's move.
+    pub fn is_pondering(&self) -> bool {
+        self.in_search() && self.pondering.load(Ordering::Relaxed)
+    }
+
+    /// The opponent played the move that is being pondered on, so the running search switches to
+    /// its normal time limits, with the clock starting now.
+    pub fn ponderhit(&self) {
+        self.pondering.store(false, Ordering::Relaxed);
+    }
+
+    /// The reply expected to the best move of the last search, taken from its principal variation.
+    pub fn ponder_move(&self) -> Option<Move> {
+        match self.diagnostics.pv.moves() {
+            [best, reply, ..] if Some(*best) == self.best_move => Some(*reply),
+            _ => None,
+        }
+    }
+
+    /// Tells the running search to stop. The best move found so far becomes available through
+    /// [`SearchHandle::best_move`] once the search threads have exited.
+    pub fn abort(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Stops the running search and returns the best move found so far.
+    pub fn force_best_move(&mut self) -> Option<Move> {
+        self.abort();
+        self.wait();
+        self.best_move
+    }
+
+    /// Replaces the transposition table with an empty one that takes up `size_mb` megabytes,
+    /// stopping any running search first.
+    pub fn resize_transposition_table(&mut self, size_mb: usize) {
+        self.abort();
+        self.wait();
+
+        // Release the old table before allocating the new one
+        self.transposition_table = Arc::new(TranspositionTable::new(1));
+        self.searchers.iter_mut().for_each(|s| s.transposition_table = self.transposition_table.clone());
+
+        self.transposition_table = Arc::new(TranspositionTable::new(size_mb));
+        self.searchers.iter_mut().for_each(|s| s.transposition_table = self.transposition_table.clone());
+    }
+
+    /// Empties the transposition table, unless a search is running, in which case nothing happens.
+    pub fn clear_transposition_table(&mut self) {
+        if self.in_search() {
+            return;
+        }
+
+        self.wait();
+        self.transposition_table.clear();
+    }
+
+    /// Stops any running search and resets the state the searchers keep between searches, for
+    /// when the next search is of a different game. The transposition table is left alone.
+    pub fn new_game(&mut self) {
+        self.abort();
+        self.wait();
+        self.searchers.iter_mut().for_each(Searcher::new_game);
+    }
+
+    /// Blocks until the running search finishes, if there is one.
+    pub fn wait(&mut self) {
+        if self.threads.is_empty() {
+            return;
+        }
+
+        self.searchers = self.threads.drain(..).map(|t| t.join().expect("search thread panicked")).collect();
+
+        let main = &self.searchers[0];
+        self.best_move = main.best_move();
+        self.diagnostics = main.diagnostics;
+        self.diagnostics.nodes = self.searchers.iter().map(|s| s.diagnostics.nodes).sum();
+    }
+}
+
+impl Default for SearchHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for SearchHandle {
+    fn drop(&mut self) {
+        self.abort();
+        self.wait();
+    }
+}
@@ -0,0 +1,267 @@
+use crate::{board::{moves::Move, piece::Piece, zobrist::Zobrist, Board}, move_gen::move_generator::MoveGenerator};
+
+use super::{diagnostics::SearchInfo, pv::{PrincipalVariation, PvTable}, Searcher};
+
+/// State of a search for a forced mate, shared by all its nodes.
+///
+/// Unlike the normal search, a mate search only answers whether the side to move at the root can
+/// force mate within a number of moves, so every node either proves or disproves the mate and
+/// there are no scores or bounds. Only the side giving mate is restricted: the defending side
+/// always tries every legal move.
+struct MateSearch {
+    root_moves: Vec<Move>,
+    checks_only: bool,
+    no_mate: NoMateTable,
+    /// The defence that last escaped mate at each ply, tried first by the next defending node
+    defences: [Move; PvTable::MAX_PLY],
+}
+
+impl Searcher {
+    /// The longest mate that can be searched for, limited by the length of the reported line.
+    const MAX_MATE_MOVES: u16 = (PrincipalVariation::MAX_LENGTH / 2) as u16;
+
+    /// Looks for the shortest forced mate in at most `max_moves` moves, one move longer each
+    /// iteration. A mate that is found becomes the best move and is reported with its line,
+    /// otherwise [`SearchInfo::NoMate`] is reported, or [`SearchInfo::MateStopped`] if the search
+    /// was stopped first.
+    pub(super) fn solve_mate(&mut self, max_moves: u16, moves: &[Move], board: &mut Board, zobrist: &Zobrist, movegen: &mut MoveGenerator) {
+        let max_moves = max_moves.clamp(1, Self::MAX_MATE_MOVES);
+        let mut search = MateSearch {
+            root_moves: moves.to_vec(),
+            checks_only: self.opts.mate_checks_only,
+            no_mate: NoMateTable::new(self.transposition_table.size_mb()),
+            defences: [Move::NULL; PvTable::MAX_PLY],
+        };
+
+        for n in 1..=max_moves {
+            match self.mate_attack(&mut search, 0, n, board, zobrist, movegen) {
+                None => {
+                    self.report(SearchInfo::MateStopped { moves: n });
+                    return;
+                },
+                Some(false) => continue,
+                Some(true) => {
+                    let plies = 2 * n - 1;
+                    self.diagnostics.depth_searched = plies as u8;
+                    self.diagnostics.evaluation = Self::IMMEDIATE_MATE_SCORE - plies as i32;
+                    self.diagnostics.pv = self.pv.line();
+                    self.diagnostics.time = self.time.elapsed();
+                    self.diagnostics.multipv = 1;
+                    self.best_move = self.diagnostics.pv.moves().first().copied();
+                    self.report(SearchInfo::Iteration(self.diagnostics));
+                    return;
+                },
+            }
+        }
+
+        self.report(SearchInfo::NoMate { moves: max_moves, checks_only: search.checks_only });
+    }
+
+    /// Whether the side to move can force mate in `moves_left` moves, or `None` if the search was
+    /// stopped. On success, the mating line is left in the PV table at `ply`.
+    fn mate_attack(&mut self, search: &mut MateSearch, ply: u8, moves_left: u16, board: &mut Board, zobrist: &Zobrist, movegen: &mut MoveGenerator) -> Option<bool> {
+        self.count_node(ply);
+        self.pv.clear(ply);
+
+        if self.should_stop() {
+            return None;
+        }
+
+        let key = board.current_state.zobrist_key;
+        let fifty_move_counter = board.current_state.fifty_move_counter;
+        if ply > 0 && fifty_move_counter >= 100 {
+            return Some(false);
+        }
+
+        // The key doesn't include the fifty move counter, so only results the rule can't have
+        // affected are shared between positions
+        let use_table = ply > 0 && (fifty_move_counter as u32) + 2 * (moves_left as u32) < 100;
+        if use_table && search.no_mate.get(key) >= moves_left {
+            return Some(false);
+        }
+
+        let moves = if ply == 0 { search.root_moves.clone() } else { movegen.generate_moves(board, false) };
+
+        // Checks are tried first, then captures. The last move has to be a check to mate, so
+        // other moves are only tried before that and when not restricted to checks.
+        let mut candidates = Vec::with_capacity(moves.len());
+        for m in moves {
+            let is_capture = board.square[m.target()].piece_type() != Piece::NONE;
+            board.make_move(m, true, zobrist);
+            let gives_check = board.in_check();
+            board.unmake_move(m, true);
+
+            if gives_check || (moves_left > 1 && !search.checks_only) {
+                candidates.push((m, gives_check, is_capture));
+            }
+        }
+        candidates.sort_by_key(|&(_, gives_check, is_capture)| (!gives_check, !is_capture));
+
+        for (m, _, _) in candidates {
+            board.make_move(m, true, zobrist);
+            let mated = self.mate_defend(search, ply + 1, moves_left, board, zobrist, movegen);
+            board.unmake_move(m, true);
+
+            if mated? {
+                self.pv.update(ply, m);
+                return Some(true);
+            }
+        }
+
+        // The root may only have tried some of its moves, so it proves nothing about the position
+        if use_table {
+            search.no_mate.store(key, moves_left);
+        }
+        Some(false)
+    }
+
+    /// Whether every defence against the last move leads to mate within the `moves_left` moves of
+    /// the attacker, counting the one just played, or `None` if the search was stopped.
+    fn mate_defend(&mut self, search: &mut MateSearch, ply: u8, moves_left: u16, board: &mut Board, zobrist: &Zobrist, movegen: &mut MoveGenerator) -> Option<bool> {
+        self.count_node(ply);
+        self.pv.clear(ply);
+
+        if self.should_stop() {
+            return None;
+        }
+
+        let mut moves = movegen.generate_moves(board, false);
+        if moves.is_empty() {
+            return Some(movegen.in_check());
+        }
+        if moves_left == 1 || board.current_state.fifty_move_counter >= 100 {
+            return Some(false);
+        }
+
+        // A defence that worked in a sibling position likely works here too
+        if let Some(i) = moves.iter().position(|&m| m == search.defences[ply as usize]) {
+            moves.swap(0, i);
+        }
+
+        for (i, m) in moves.into_iter().enumerate() {
+            board.make_move(m, true, zobrist);
+            let mated = self.mate_attack(search, ply + 1, moves_left - 1, board, zobrist, movegen);
+            board.unmake_move(m, true);
+
+            if !mated? {
+                search.defences[ply as usize] = m;
+                return Some(false);
+            }
+
+            // All defences lose, so the line continues with the first one
+            if i == 0 {
+                self.pv.update(ply, m);
+            }
+        }
+
+        Some(true)
+    }
+}
+
+
+/// Positions with the attacker to move that are known to have no mate in some number of moves.
+///
+/// Each key maps to a single slot, and a new position always replaces the one stored there.
+struct NoMateTable {
+    entries: Vec<(u64, u16)>,
+}
+
+impl NoMateTable {
+    /// Creates a table that takes up a quarter of `hash_mb` megabytes.
+    fn new(hash_mb: usize) -> Self {
+        let n_entries = (hash_mb.max(1) * 1024 * 1024 / 4 / std::mem::size_of::<(u64, u16)>()).max(1);
+        Self { entries: vec![(0, 0); n_entries] }
+    }
+
+    /// The number of moves `key` is known to have no mate in, or 0 if it isn't stored.
+    fn get(&self, key: u64) -> u16 {
+        match self.entries[(key % self.entries.len() as u64) as usize] {
+            (stored, moves) if stored == key => moves,
+            _ => 0,
+        }
+    }
+
+    fn store(&mut self, key: u64, moves: u16) {
+        let moves = moves.max(self.get(key));
+        let len = self.entries.len() as u64;
+        self.entries[(key % len) as usize] = (key, moves);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicBool, mpsc, Arc};
+
+    use crate::{board::{moves::Move, zobrist::Zobrist, Board}, move_gen::{magics, move_generator::MoveGenerator}, precomp, search::{diagnostics::SearchInfo, options::SearchOptions, transpositions::TranspositionTable, Searcher}};
+    use super::NoMateTable;
+
+    fn solve(fen: &str, mate: u16, checks_only: bool) -> (Searcher, Vec<SearchInfo>) {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let mut board = Board::load_position(Some(String::from(fen)), &mut zobrist);
+        let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), Arc::new(TranspositionTable::default()), 0);
+        let (sender, receiver) = mpsc::channel();
+        searcher.info = Some(sender);
+
+        let opts = SearchOptions { movetime: None, mate: Some(mate), mate_checks_only: checks_only, ..Default::default() };
+        searcher.begin_search(opts, &mut board, &zobrist, &mut MoveGenerator::default());
+        let info = receiver.try_iter().collect();
+        (searcher, info)
+    }
+
+    #[test]
+    fn test_back_rank_mate() {
+        let (s, _) = solve("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", 3, false);
+        assert_eq!(s.diagnostics.mate_in(), 1);
+        assert_eq!(s.best_move(), Some(Move::from_start_end(3, 59)));
+        assert_eq!(s.diagnostics.pv.len(), 1);
+    }
+
+    #[test]
+    fn test_quiet_first_move() {
+        // Kb6 followed by Rh8 mates, while checking right away lets the king escape
+        let fen = "k7/8/8/1K6/8/8/8/7R w - - 0 1";
+        let (s, _) = solve(fen, 3, false);
+        assert_eq!(s.diagnostics.mate_in(), 2);
+        assert_eq!(s.diagnostics.pv.len(), 3);
+
+        let (_, info) = solve(fen, 1, false);
+        assert!(matches!(info.last(), Some(SearchInfo::NoMate { moves: 1, checks_only: false })));
+
+        let (_, info) = solve(fen, 2, true);
+        assert!(matches!(info.last(), Some(SearchInfo::NoMate { moves: 2, checks_only: true })));
+    }
+
+    #[test]
+    fn test_stopped_without_result() {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let mut board = Board::load_position(Some(String::from("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3")), &mut zobrist);
+        let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), Arc::new(TranspositionTable::default()), 0);
+        let (sender, receiver) = mpsc::channel();
+        searcher.info = Some(sender);
+
+        let opts = SearchOptions { movetime: None, mate: Some(5), nodes: Some(1000), ..Default::default() };
+        searcher.begin_search(opts, &mut board, &zobrist, &mut MoveGenerator::default());
+        let info: Vec<SearchInfo> = receiver.try_iter().collect();
+        assert!(matches!(info.last(), Some(SearchInfo::MateStopped { .. })));
+        assert!(!info.iter().any(|i| matches!(i, SearchInfo::Iteration(_))));
+    }
+
+    #[test]
+    fn test_no_mate_table() {
+        let mut table = NoMateTable::new(1);
+        let n = table.entries.len() as u64;
+        table.store(7, 3);
+        table.store(7, 2);
+        assert_eq!(table.get(7), 3);
+
+        // A position hashing to the same slot replaces the old one
+        table.store(7 + n, 1);
+        assert_eq!(table.get(7), 0);
+        assert_eq!(table.get(7 + n), 1);
+    }
+}
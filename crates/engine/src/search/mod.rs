@@ -1,48 +1,134 @@
-use std::time::Instant;
+use std::{sync::{atomic::{AtomicBool, AtomicU64, Ordering}, mpsc::Sender, Arc}, thread, time::Duration};
 
 use crate::{board::{coord::Coord, moves::Move, piece::Piece, zobrist::Zobrist, Board}, color::{Black, White}, eval::Evaluation, move_gen::{magics::Magics, move_generator::MoveGenerator}, precomp::Precomputed};
 
-use self::{diagnostics::SearchDiagnostics, options::SearchOptions, ordering::MoveOrdering, repetition::RepetitionTable, see::static_exchange_eval, transpositions::{TranspositionNodeType, TranspositionTable}};
+use self::{diagnostics::{SearchDiagnostics, SearchInfo}, options::SearchOptions, ordering::MoveOrdering, picker::MovePicker, pv::PvTable, reductions::ReductionTable, repetition::RepetitionTable, see::static_exchange_eval, stats::count_stat, time_manager::TimeManager, trace::Tracer, transpositions::{TranspositionNodeType, TranspositionTable}};
 
 pub mod options;
 pub mod diagnostics;
 pub mod repetition;
 pub mod transpositions;
 pub mod ordering;
+pub mod picker;
 pub mod see;
-
-pub struct Searcher<'a> {
+pub mod handle;
+pub mod time_manager;
+pub mod pv;
+pub mod reductions;
+pub mod mate;
+pub mod stats;
+pub mod trace;
+
+pub struct Searcher {
     pub diagnostics: SearchDiagnostics,
     pub in_search: bool,
-    pub transposition_table: TranspositionTable<4_194_304>, // 64 MB: 4_194_304
+    pub transposition_table: Arc<TranspositionTable>,
+    pub thread_id: usize,
     best_move: Option<Move>,
     backup_move: Move,
-    eval: Option<Evaluation<'a>>,
-    start_time: Instant,
+    time: TimeManager,
     opts: SearchOptions,
+    stop: Arc<AtomicBool>,
+    pv: PvTable,
+    /// Nodes searched by the helper threads, which they add to in batches
+    helper_nodes: Arc<AtomicU64>,
+    /// Where the main thread sends its progress, if anyone is listening
+    info: Option<Sender<SearchInfo>>,
+    /// Set while a ponder search is waiting for the opponent's move, cleared on ponderhit
+    pondering: Arc<AtomicBool>,
+    /// Plies at which a null move cutoff is being verified. Null moves aren't tried at those
+    /// plies, but are below them.
+    null_move_verifications: [bool; PvTable::MAX_PLY],
+    reductions: ReductionTable,
+    /// Static evaluation of the nodes on the current line by ply, `None` when in check
+    static_evals: [Option<i32>; PvTable::MAX_PLY],
+    /// Move skipped at each ply while testing whether the transposition table move is singular
+    excluded_moves: [Move; PvTable::MAX_PLY],
+    /// Records the search tree of the main thread when asked to by the options
+    tracer: Option<Tracer>,
+    /// Killers and histories, kept from one search to the next within a game. `None` before the
+    /// first search of a game and while a search has taken them.
+    ordering: Option<MoveOrdering>,
+    /// Number of moves played in the game before the root of the last search, and its key
+    last_root: Option<(usize, u64)>,
+    #[cfg(feature = "search-stats")]
+    pub stats: stats::SearchStats,
 }
 
-impl<'a> Searcher<'a> {
+impl Searcher {
     const IMMEDIATE_MATE_SCORE: i32 = 1000000;
     const POSITIVE_INFINITY: i32 = i32::MAX;
     const NEGATIVE_INFINITY: i32 = -Self::POSITIVE_INFINITY;
     const MAX_EXTENSIONS: u8 = 16;
     const ASPIRATION_WINDOW_SIZE: i32 = 40;
-
-    pub fn new() -> Self {
+    /// Helper threads add their node count to the shared counter every this many nodes.
+    const NODE_BATCH_SIZE: u64 = 1024;
+    /// How long a search has to run before the root move being searched is reported.
+    const CURRMOVE_DELAY: Duration = Duration::from_secs(3);
+    /// How often a finished ponder search checks for the ponderhit.
+    const PONDER_POLL_INTERVAL: Duration = Duration::from_millis(1);
+    const NULL_MOVE_MIN_DEPTH: u8 = 3;
+    const NULL_MOVE_REDUCTION: u8 = 3;
+    /// Null move cutoffs are verified when the side to move has at most this many pieces besides
+    /// pawns and the king, as zugzwang becomes likely.
+    const NULL_MOVE_VERIFICATION_PIECES: u32 = 2;
+    const REVERSE_FUTILITY_MAX_DEPTH: u8 = 6;
+    const REVERSE_FUTILITY_MARGIN: i32 = 150;
+    const RAZORING_MAX_DEPTH: u8 = 3;
+    const RAZORING_MARGIN: i32 = 300;
+    const FUTILITY_MAX_DEPTH: u8 = 3;
+    const FUTILITY_MARGIN: i32 = 200;
+    const MOVE_COUNT_PRUNING_MAX_DEPTH: u8 = 3;
+    /// Quiet moves after the first `BASE + FACTOR * depth^2` moves are pruned.
+    const MOVE_COUNT_PRUNING_BASE: usize = 4;
+    const MOVE_COUNT_PRUNING_FACTOR: usize = 3;
+    /// How far above alpha a capture must be able to bring the evaluation to be searched in
+    /// quiescence search.
+    const DELTA_MARGIN: i32 = 200;
+    const LMR_MIN_DEPTH: u8 = 3;
+    /// Every this much history score reduces a move by one ply less.
+    const LMR_HISTORY_DIVISOR: i32 = MoveOrdering::MAX_HISTORY;
+    const SINGULAR_MIN_DEPTH: u8 = 8;
+    /// How far below the transposition table score all other moves must fail, per ply of depth,
+    /// for the transposition table move to be singular.
+    const SINGULAR_MARGIN: i32 = 3;
+    const MAX_TRIED_MOVES: usize = 64;
+
+    /// Creates a new searcher that will exit its search as soon as `stop` is set.
+    ///
+    /// Searchers on different threads can share the same transposition table. The searcher with a
+    /// `thread_id` of 0 is the main thread; helpers start at staggered depths so that they fill the
+    /// table with different parts of the tree.
+    pub fn new(stop: Arc<AtomicBool>, transposition_table: Arc<TranspositionTable>, thread_id: usize) -> Self {
         Self {
             diagnostics: SearchDiagnostics::default(),
-            transposition_table: TranspositionTable::new(),
+            transposition_table,
+            thread_id,
             best_move: None,
             backup_move: Move::NULL,
             in_search: false,
-            eval: None,
-            start_time: Instant::now(),
+            time: TimeManager::new(&SearchOptions::default(), true),
             opts: SearchOptions::default(),
+            stop,
+            pv: PvTable::new(),
+            helper_nodes: Arc::new(AtomicU64::new(0)),
+            info: None,
+            pondering: Arc::new(AtomicBool::new(false)),
+            null_move_verifications: [false; PvTable::MAX_PLY],
+            reductions: ReductionTable::new(SearchOptions::default().lmr_base, SearchOptions::default().lmr_divisor),
+            static_evals: [None; PvTable::MAX_PLY],
+            excluded_moves: [Move::NULL; PvTable::MAX_PLY],
+            tracer: None,
+            ordering: None,
+            last_root: None,
+            #[cfg(feature = "search-stats")]
+            stats: stats::SearchStats::default(),
         }
     }
 
-    /// Starts searching for the best move in the position depending on whose turn it is to move. 
+    /// Searches for the best move in the position depending on whose turn it is to move, blocking
+    /// until the search finishes. See [`SearchHandle`](handle::SearchHandle) to search on a
+    /// background thread.
     ///
     /// Assumes that that the position has valid moves (not stalemate or checkmate).
     pub fn begin_search(
@@ -55,72 +141,164 @@ impl<'a> Searcher<'a> {
         self.opts = opts;
         self.init();
 
-        let moves = movegen.generate_moves(board, false);
+        if !self.reductions.uses(self.opts.lmr_base, self.opts.lmr_divisor) {
+            self.reductions = ReductionTable::new(self.opts.lmr_base, self.opts.lmr_divisor);
+        }
+        self.time = TimeManager::new(&self.opts, board.white_to_move);
+
+        // Deterministic searches start from scratch, others carry on from the previous search if
+        // its root was reached by playing moves from there
+        let root_ply = board.move_log.len();
+        let plies_advanced = self.last_root.and_then(|(last_ply, last_key)| {
+            let plies = root_ply.checked_sub(last_ply)?;
+            let index = board.game_state_history.len().checked_sub(plies + 1)?;
+            (board.game_state_history[index].zobrist_key == last_key).then_some(plies)
+        });
+        if self.opts.deterministic {
+            self.transposition_table.clear();
+            self.new_game();
+        } else if plies_advanced.is_none() {
+            self.new_game();
+        } else if let Some(ordering) = &mut self.ordering {
+            ordering.age(plies_advanced);
+        }
+        self.last_root = Some((root_ply, board.current_state.zobrist_key));
+        self.tracer = self.opts.trace.clone().filter(|_| self.thread_id == 0).map(Tracer::new);
+
+        let moves = self.root_moves(board, movegen);
         self.backup_move = moves[0];
 
+        if let Some(mate) = self.opts.mate {
+            // Helpers have nothing to contribute to a mate search
+            if self.thread_id == 0 {
+                self.solve_mate(mate, &moves, board, zobrist, movegen);
+            }
+            self.best_move = self.best_move.or(Some(self.backup_move));
+        } else {
+            self.iterative_deepening(&moves, board, zobrist, movegen);
+        }
+
+        // The best move of a ponder search may only be played after the ponderhit
+        while self.time.pondering() && !self.stop.load(Ordering::Relaxed) {
+            self.check_ponderhit();
+            thread::sleep(Self::PONDER_POLL_INTERVAL);
+        }
+
+        if let Some(tracer) = self.tracer.take() {
+            if let Err(e) = tracer.write() {
+                eprintln!("failed to write the search tree: {}", e);
+            }
+        }
+
+        #[cfg(feature = "search-stats")]
+        if self.thread_id == 0 {
+            eprintln!("{}", self.stats);
+        }
+
+        self.in_search = false;
+    }
+
+    /// Searches the root at increasing depths until a limit is reached, keeping the best move of
+    /// the last iteration that found one.
+    fn iterative_deepening(&mut self, moves: &[Move], board: &mut Board, zobrist: &Zobrist, movegen: &mut MoveGenerator) {
         let mut repetition_table = RepetitionTable::new(board);
-        let mut ordering = MoveOrdering::new();
-        let mut score: i32;
-        let mut alpha = Self::NEGATIVE_INFINITY;
-        let mut beta = Self::POSITIVE_INFINITY;
-        let mut left_window = Self::ASPIRATION_WINDOW_SIZE;
-        let mut right_window = Self::ASPIRATION_WINDOW_SIZE;
-        let mut best_move_this_iter = None;
-        let mut depth = 1;
+        let mut ordering = self.ordering.take().unwrap_or_default();
+        let mut depth = 1 + (self.thread_id % 2) as u8;
+        let max_depth = self.opts.depth.map_or(u8::MAX - 1, |d| d.clamp(1, u8::MAX as u16 - 1) as u8);
+
+        // With MultiPV, each iteration searches the root once per line, leaving out the best moves
+        // of the lines before it.
+        let n_lines = self.opts.multipv.clamp(1, moves.len());
+        let mut line_scores: Vec<Option<i32>> = vec![None; n_lines];
 
         // Iterative Deepening + Aspiration Windows
-        while depth < 255 {
-            (score, best_move_this_iter) = self.search_root(
-                depth,
-                alpha,
-                beta,
-                &moves,
-                board,
-                &mut ordering,
-                &mut repetition_table,
-                zobrist,
-                movegen,
-            );
-
-            // Search was cancelled
-            if !self.in_search {
-                // Even if we end with a partial search, since the best move was considered first
-                // we can trust the best move from the partial search is equal or better.
-                if let Some(m) = best_move_this_iter {
-                    self.best_move = Some(m);
-                    self.diagnostics.evaluation = score;
-                } 
+        'deepening: while depth <= max_depth {
+            let mut excluded = Vec::with_capacity(n_lines);
+            let mut lines = Vec::with_capacity(n_lines);
+
+            for line in 0..n_lines {
+                let mut left_window = Self::ASPIRATION_WINDOW_SIZE;
+                let mut right_window = Self::ASPIRATION_WINDOW_SIZE;
+                let (mut alpha, mut beta) = match line_scores[line] {
+                    Some(score) => (score - left_window, score + right_window),
+                    None => (Self::NEGATIVE_INFINITY, Self::POSITIVE_INFINITY),
+                };
+
+                let (score, best_move_this_iter) = loop {
+                    let (score, best_move_this_iter) = self.search_root(
+                        depth,
+                        alpha,
+                        beta,
+                        moves,
+                        &excluded,
+                        board,
+                        &mut ordering,
+                        &mut repetition_table,
+                        zobrist,
+                        movegen,
+                    );
+
+                    // Search was cancelled
+                    if !self.in_search {
+                        // Lines that were completed are still better informed than the last
+                        // iteration
+                        if line > 0 {
+                            self.finish_iteration(lines, &mut line_scores);
+                        }
 
-                if self.best_move.is_none() {
-                    self.best_move = Some(self.backup_move);
-                }
+                        // Even if we end with a partial search, since the best move was considered
+                        // first we can trust the best move from the partial search is equal or
+                        // better. It is reported as coming from the depth it was searched at.
+                        if let Some(m) = best_move_this_iter.filter(|_| line == 0) {
+                            self.best_move = Some(m);
+                            self.diagnostics.depth_searched = depth;
+                            self.diagnostics.evaluation = score;
+                            self.diagnostics.pv = self.pv.line();
+                            self.diagnostics.time = self.time.elapsed();
+                            self.diagnostics.multipv = 1;
+                            self.report(SearchInfo::Iteration(self.diagnostics));
+                        }
 
-                break;
-            }
+                        if self.best_move.is_none() {
+                            self.best_move = Some(self.backup_move);
+                        }
 
-            // If the score is outside the current aspiration window, search again with wider
-            // window
-            if score <= alpha {
-                alpha -= left_window;
-                left_window *= 2;
-                best_move_this_iter = None;
-                continue;
-            } else if score >= beta {
-                beta += right_window;
-                right_window *= 2;
-                best_move_this_iter = None;
-                continue;
+                        break 'deepening;
+                    }
+
+                    // If the score is outside the current aspiration window, search again with
+                    // wider window
+                    if score <= alpha {
+                        alpha -= left_window;
+                        left_window *= 2;
+                    } else if score >= beta {
+                        beta += right_window;
+                        right_window *= 2;
+                    } else {
+                        break (score, best_move_this_iter);
+                    }
+                };
+
+                let diagnostics = SearchDiagnostics {
+                    depth_searched: depth,
+                    evaluation: score,
+                    hashfull: self.transposition_table.hashfull(),
+                    pv: self.pv.line(),
+                    time: self.time.elapsed(),
+                    ..self.diagnostics
+                };
+                lines.push((diagnostics, best_move_this_iter));
+
+                if let Some(m) = best_move_this_iter {
+                    excluded.push(m);
+                }
             }
 
-            self.best_move = best_move_this_iter;
-            self.diagnostics.depth_searched = depth;
-            self.diagnostics.evaluation = score;
+            self.finish_iteration(lines, &mut line_scores);
+            #[cfg(feature = "search-stats")]
+            self.stats.end_iteration(self.diagnostics.nodes);
 
-            best_move_this_iter = None;
-            left_window = Self::ASPIRATION_WINDOW_SIZE;
-            right_window = Self::ASPIRATION_WINDOW_SIZE;
-            alpha = score - left_window;
-            beta = score + right_window;
+            let score = line_scores[0].unwrap_or_default();
 
             // Exit if mate was found
             if score.abs() > Self::IMMEDIATE_MATE_SCORE - 1000 
@@ -128,10 +306,52 @@ impl<'a> Searcher<'a> {
                 break;
             }
 
+            // Don't start another iteration if it is unlikely to finish in time
+            self.check_ponderhit();
+            if self.time.soft_limit_reached() {
+                break;
+            }
+
             depth += 1;
         }
 
-        self.in_search = false;
+        self.ordering = Some(ordering);
+    }
+
+    /// The legal moves to search at the root, restricted by `search_moves` and `excluded_moves`.
+    /// If that leaves nothing to search, every legal move is searched instead.
+    fn root_moves(&self, board: &Board, movegen: &mut MoveGenerator) -> Vec<Move> {
+        let moves = movegen.generate_moves(board, false);
+        let allowed: Vec<Move> = moves.iter().copied().filter(|m| {
+            self.opts.search_moves.as_ref().is_none_or(|search_moves| search_moves.contains(m))
+            && !self.opts.excluded_moves.contains(m)
+        }).collect();
+
+        if allowed.is_empty() { moves } else { allowed }
+    }
+
+    /// Ranks the lines searched in an iteration by score, as a later line can come out better than
+    /// an earlier one, then reports them and takes the best move from the first.
+    fn finish_iteration(&mut self, mut lines: Vec<(SearchDiagnostics, Option<Move>)>, line_scores: &mut [Option<i32>]) {
+        lines.sort_by_key(|(diagnostics, _)| -diagnostics.evaluation);
+        let (nodes, time) = (self.diagnostics.nodes, self.time.elapsed());
+
+        for (i, (mut diagnostics, m)) in lines.into_iter().enumerate() {
+            diagnostics.multipv = i + 1;
+            diagnostics.nodes = nodes;
+            diagnostics.time = time;
+            line_scores[i] = Some(diagnostics.evaluation);
+            self.report(SearchInfo::Iteration(diagnostics));
+
+            if i == 0 {
+                self.best_move = m;
+                self.diagnostics = diagnostics;
+
+                if let Some(m) = m {
+                    self.time.update(m, diagnostics.evaluation);
+                }
+            }
+        }
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -141,19 +361,20 @@ impl<'a> Searcher<'a> {
         mut alpha: i32,
         mut beta: i32,
         moves: &[Move],
+        excluded: &[Move],
         board: &mut Board,
         ordering: &mut MoveOrdering,
         repetition_table: &mut RepetitionTable,
         zobrist: &Zobrist,
         movegen: &mut MoveGenerator,
     ) -> (i32, Option<Move>) {
-        // If this position was already searched and is stored in the transposition table,
-        // retrieve the value and immediately search at the next depth.
+        self.trace(|t| t.enter(0, Move::NULL, depth, alpha, beta));
+        self.pv.clear(0);
+        self.static_evals[0] = None;
+        self.diagnostics.seldepth = 0;
+
+        // The root is always a PV node, so the transposition table is never used for a cutoff here
         let zobrist_key = board.current_state.zobrist_key;
-        if let Some(tt_eval) = self.transposition_table.lookup(zobrist_key, depth, 0, alpha, beta) {
-            let entry = self.transposition_table.get(zobrist_key);
-            return (tt_eval, Some(entry.m));
-        }
 
         // Order moves and ensure the best move from the previous search is considered first.
         // This way, partial searches can be used as they will either agree on the best move, 
@@ -172,27 +393,52 @@ impl<'a> Searcher<'a> {
         let mut best_score = Self::NEGATIVE_INFINITY;
         let mut eval_bound = TranspositionNodeType::UpperBound;
 
-        for (i, m) in ordered_moves.into_iter().enumerate() {
+        for (i, m) in ordered_moves.into_iter().filter(|m| !excluded.contains(m)).enumerate() {
+            if self.time.elapsed() >= Self::CURRMOVE_DELAY {
+                self.report(SearchInfo::CurrentMove { depth, m, number: i + 1 });
+            }
+
             let captured_ptype = board.square[m.target()].piece_type();
             let is_capture = captured_ptype != Piece::NONE;
+            let history = if is_capture { 0 } else { ordering.quiet_history(board, m, 0) };
 
+            ordering.record_move(0, board, m);
             board.make_move(m, true, zobrist);
 
             // Check extensions
             let extension = if board.in_check() { 1 } else { 0 };
+            count_stat!(self.extensions, extension);
 
-            let mut eval = 0;
-            let mut needs_full_search = true;
+            let mut eval;
 
-            // Late move reductions: reduce search depth when searching later moves because they
-            // are likely to be bad.
-            if extension == 0 && depth >= 3 && i >= 3 && !is_capture {
-                eval = -self.search(1, depth - 2, -alpha - 1, -alpha, extension, board, ordering, repetition_table, m, is_capture, zobrist, movegen);
-                needs_full_search = eval > alpha;
-            }
-
-            if needs_full_search {
+            // Principal variation search: the first move is searched with the full window. The
+            // rest are expected to be worse, so they are only scouted with a null window to prove
+            // that, and searched again with the full window if they turn out better.
+            if i == 0 {
                 eval = -self.search(1, depth - 1 + extension, -beta, -alpha, extension, board, ordering, repetition_table, m, is_capture, zobrist, movegen);
+            } else {
+                // Late move reductions: reduce search depth when searching later moves because
+                // they are likely to be bad.
+                let reduction = if extension == 0 && !is_capture && !m.is_promotion() && depth >= Self::LMR_MIN_DEPTH {
+                    self.late_move_reduction(depth, i, history, false, true, false)
+                } else {
+                    0
+                };
+
+                eval = Self::NEGATIVE_INFINITY;
+                if reduction > 0 {
+                    eval = -self.search(1, depth - 1 - reduction, -alpha - 1, -alpha, extension, board, ordering, repetition_table, m, is_capture, zobrist, movegen);
+                    count_stat!(self.lmr_searches);
+                    count_stat!(self.lmr_researches, eval > alpha);
+                }
+
+                if reduction == 0 || eval > alpha {
+                    eval = -self.search(1, depth - 1 + extension, -alpha - 1, -alpha, extension, board, ordering, repetition_table, m, is_capture, zobrist, movegen);
+                }
+
+                if eval > alpha && eval < beta {
+                    eval = -self.search(1, depth - 1 + extension, -beta, -alpha, extension, board, ordering, repetition_table, m, is_capture, zobrist, movegen);
+                }
             }
 
             board.unmake_move(m, true);
@@ -208,21 +454,53 @@ impl<'a> Searcher<'a> {
                 if eval > alpha {
                     best_move = Some(m);
                     alpha = eval;
+                    self.pv.update(0, m);
                     eval_bound = TranspositionNodeType::Exact;
                 }
             }
         }
 
-        // Store this position in the transposition table
-        if let Some(m) = best_move {
+        // Store this position in the transposition table, unless moves were left out for MultiPV
+        if let Some(m) = best_move.filter(|_| excluded.is_empty()) {
             self.transposition_table.store(zobrist_key, depth, 0, best_score, eval_bound, m);
         }
 
+        let in_search = self.in_search;
+        self.trace(|t| t.exit(Some(best_score).filter(|_| in_search)));
         (best_score, best_move)
     }
 
+    /// Searches a node, recording it in the search tree when tracing.
     #[allow(clippy::too_many_arguments)]
     fn search(
+        &mut self,
+        depth: u8,
+        depth_remaining: u8,
+        alpha: i32,
+        beta: i32,
+        n_extensions: u8,
+        board: &mut Board,
+        ordering: &mut MoveOrdering,
+        repetition_table: &mut RepetitionTable,
+        prev_move: Move,
+        prev_move_was_capture: bool,
+        zobrist: &Zobrist,
+        movegen: &mut MoveGenerator,
+    ) -> i32 {
+        if self.tracer.is_none() {
+            return self.search_node(depth, depth_remaining, alpha, beta, n_extensions, board, ordering, repetition_table, prev_move, prev_move_was_capture, zobrist, movegen);
+        }
+
+        self.trace(|t| t.enter(depth, prev_move, depth_remaining, alpha, beta));
+        let score = self.search_node(depth, depth_remaining, alpha, beta, n_extensions, board, ordering, repetition_table, prev_move, prev_move_was_capture, zobrist, movegen);
+        let in_search = self.in_search;
+        self.trace(|t| t.exit(Some(score).filter(|_| in_search)));
+
+        score
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn search_node(
         &mut self,
         depth: u8,
         depth_remaining: u8,
@@ -237,91 +515,276 @@ impl<'a> Searcher<'a> {
         zobrist: &Zobrist,
         movegen: &mut MoveGenerator,
     ) -> i32 {
-        // Abort search if we've run out of time
-        if let Some(time) = self.opts.movetime {
-            if time <= Instant::now().duration_since(self.start_time).as_millis() as u32 {
-                self.in_search = false;
-                return 0;
-            }
-        }
+        self.count_node(depth);
+        self.pv.clear(depth);
+
+        // PV nodes are searched with an open window, all others with a null window where all
+        // that matters is whether the score is above or below beta.
+        let is_pv = beta > alpha + 1;
 
-        // Consider draw cases
-        if board.current_state.fifty_move_counter >= 100 || repetition_table.contains(board.current_state.zobrist_key) {
+        // Set while searching this node again without one move to test whether it is singular.
+        // Such a search must not use or overwrite the transposition table entry for the node.
+        let excluded_move = self.excluded_moves.get(depth as usize).copied().unwrap_or(Move::NULL);
+
+        // Abort search if we've run out of time or have been told to stop
+        if self.should_stop() {
+            self.in_search = false;
             return 0;
         }
 
+        // Consider draw cases. Checkmate takes precedence over the fifty move rule.
+        let irreversible = prev_move == Move::NULL || board.current_state.fifty_move_counter == 0;
+        if repetition_table.is_draw(board.current_state.zobrist_key, irreversible) {
+            self.trace(|t| t.note_pruning("repetition"));
+            return self.draw_score(depth);
+        }
+        if board.current_state.fifty_move_counter >= 100
+        && !(board.in_check() && movegen.generate_moves(board, false).is_empty()) {
+            self.trace(|t| t.note_pruning("fifty move rule"));
+            return self.draw_score(depth);
+        }
+
         // If a faster mating sequence is found, skip this position
         alpha = alpha.max(-Self::IMMEDIATE_MATE_SCORE + depth as i32);
         beta = beta.min(Self::IMMEDIATE_MATE_SCORE - depth as i32);
         if alpha >= beta {
+            self.trace(|t| t.note_pruning("mate distance"));
             return alpha;
         }
 
         // Check if we've already come across this position. If so, retrieve the evaluation and
-        // continue to the next position
+        // continue to the next position. This is skipped at PV nodes so that the PV and its score
+        // come from an actual search rather than a possibly stale or shallower entry.
         let zobrist_key = board.current_state.zobrist_key;
-        if let Some(tt_eval) = self.transposition_table.lookup(zobrist_key, depth_remaining, depth, alpha, beta) {
-            return tt_eval;
+        if self.tracer.is_some() && self.transposition_table.get(zobrist_key).is_some() {
+            self.trace(|t| t.note_tt_hit());
+        }
+        if !is_pv && excluded_move == Move::NULL {
+            count_stat!(self.tt_probes);
+            count_stat!(self.tt_hits, self.transposition_table.get(zobrist_key).is_some());
+            if let Some(tt_eval) = self.transposition_table.lookup(zobrist_key, depth_remaining, depth, alpha, beta) {
+                count_stat!(self.tt_cutoffs);
+                self.trace(|t| t.note_pruning("tt cutoff"));
+                return tt_eval;
+            }
         }
 
         // Once we hit a leaf node, perform static evaluation of the position
         if depth_remaining == 0 {
-            return Self::quiescence_search(alpha, beta, board, ordering, zobrist, movegen);
+            self.trace(|t| t.note_pruning("quiescence"));
+            return self.quiescence_search(depth, alpha, beta, board, ordering, zobrist, movegen);
         }
 
-        let moves = movegen.generate_moves(board, false);
+        let static_eval = if board.in_check() {
+            None
+        } else {
+            Some(Evaluation::new(board).evaluate::<White, Black>() * if board.white_to_move { 1 } else { -1 })
+        };
 
-        // Consider checkmate and stalemate cases
-        if moves.is_empty() {
-            if movegen.in_check() {
-                return -(Self::IMMEDIATE_MATE_SCORE - depth as i32);
-            } else {
-                return 0;
+        // The position is improving if the static evaluation went up since our previous move
+        if let Some(e) = self.static_evals.get_mut(depth as usize) {
+            *e = static_eval;
+        }
+        let improving = depth >= 2 && match (static_eval, self.static_evals.get(depth as usize - 2)) {
+            (Some(eval), Some(Some(prev))) => eval > *prev,
+            _ => false,
+        };
+
+        // Forward pruning. Only done at non-PV nodes that aren't in check.
+        let pruning_eval = static_eval.filter(|_| !is_pv && excluded_move == Move::NULL && beta.abs() < Self::IMMEDIATE_MATE_SCORE - 1000);
+
+        if let Some(static_eval) = pruning_eval {
+            // Reverse futility pruning: at shallow depths, a static evaluation this far above beta
+            // is unlikely to drop below it.
+            if self.opts.reverse_futility
+            && depth_remaining <= Self::REVERSE_FUTILITY_MAX_DEPTH
+            && static_eval - Self::REVERSE_FUTILITY_MARGIN * depth_remaining as i32 >= beta {
+                self.trace(|t| t.note_pruning("reverse futility"));
+                return beta;
+            }
+
+            // Razoring: at shallow depths, a static evaluation this far below alpha is unlikely to
+            // be recovered by quiet moves, so only look at captures.
+            if self.opts.razoring
+            && depth_remaining <= Self::RAZORING_MAX_DEPTH
+            && static_eval + Self::RAZORING_MARGIN * (depth_remaining as i32) < alpha {
+                let eval = self.quiescence_search(depth, alpha - 1, alpha, board, ordering, zobrist, movegen);
+                if eval < alpha {
+                    self.trace(|t| t.note_pruning("razoring"));
+                    return alpha;
+                }
+            }
+
+            // Null move pruning: if passing the turn and searching at a reduced depth still fails
+            // high, a real move almost certainly would too. This assumption breaks in zugzwang, so
+            // it isn't tried without pieces and the cutoff is verified in endings.
+            let non_pawn_pieces = board.friendly_non_pawn_pieces();
+            if self.opts.null_move
+            && !self.null_move_verifications.get(depth as usize).copied().unwrap_or(true)
+            && prev_move != Move::NULL
+            && depth_remaining >= Self::NULL_MOVE_MIN_DEPTH
+            && non_pawn_pieces > 0
+            && static_eval >= beta {
+                let reduced_depth = depth_remaining.saturating_sub(1 + Self::NULL_MOVE_REDUCTION + depth_remaining / 4);
+
+                ordering.record_move(depth, board, Move::NULL);
+                board.make_null_move(zobrist);
+                let eval = -self.search(depth + 1, reduced_depth, -beta, -beta + 1, n_extensions, board, ordering, repetition_table, Move::NULL, true, zobrist, movegen);
+                board.unmake_null_move();
+
+                if !self.in_search {
+                    return 0;
+                }
+
+                if eval >= beta {
+                    // The verification searches this node again, which pushes its key before
+                    // searching any moves
+                    let verified = if non_pawn_pieces <= Self::NULL_MOVE_VERIFICATION_PIECES && reduced_depth > 0 {
+                        self.null_move_verifications[depth as usize] = true;
+                        let eval = self.search(depth, reduced_depth, beta - 1, beta, n_extensions, board, ordering, repetition_table, prev_move, prev_move_was_capture, zobrist, movegen);
+                        self.null_move_verifications[depth as usize] = false;
+
+                        if !self.in_search {
+                            return 0;
+                        }
+
+                        eval >= beta
+                    } else {
+                        true
+                    };
+
+                    if verified {
+                        self.trace(|t| t.note_pruning("null move"));
+                        return beta;
+                    }
+                }
+            }
+        }
+
+        // Moves are generated and ordered lazily as they are picked
+        let mut picker = MovePicker::new(self.transposition_table.get_stored_move(zobrist_key), depth, ordering);
+
+        // Singular extensions: if the transposition table move is much better than every other
+        // move, as shown by a reduced search without it failing low, it is extended.
+        let mut singular_move = Move::NULL;
+        if excluded_move == Move::NULL && depth_remaining >= Self::SINGULAR_MIN_DEPTH && (depth as usize) < PvTable::MAX_PLY {
+            let entry = self.transposition_table.get(zobrist_key).filter(|e| {
+                e.node_type != TranspositionNodeType::UpperBound
+                && e.depth + 3 >= depth_remaining
+                && e.eval.abs() < Self::IMMEDIATE_MATE_SCORE - 1000
+            }).filter(|_| picker.has_tt_move(board, movegen));
+
+            if let Some(entry) = entry {
+                let singular_beta = entry.eval - Self::SINGULAR_MARGIN * depth_remaining as i32;
+
+                // As with the null move verification, the key is pushed by the search itself
+                self.excluded_moves[depth as usize] = entry.m;
+                let eval = self.search(depth, (depth_remaining - 1) / 2, singular_beta - 1, singular_beta, n_extensions, board, ordering, repetition_table, prev_move, prev_move_was_capture, zobrist, movegen);
+                self.excluded_moves[depth as usize] = Move::NULL;
+
+                if !self.in_search {
+                    return 0;
+                }
+
+                if eval < singular_beta {
+                    singular_move = entry.m;
+                }
             }
         }
 
         // Update repetition table
-        let was_pawn_move = board.square[prev_move.target()].piece_type() == Piece::PAWN;
-        repetition_table.push(board.current_state.zobrist_key, prev_move_was_capture || was_pawn_move);
+        repetition_table.push(board.current_state.zobrist_key, irreversible);
 
         let mut best_move = Move::NULL;
         let mut best_score = Self::NEGATIVE_INFINITY;
         let mut eval_bound = TranspositionNodeType::UpperBound;
 
-        // Order moves
-        let ordered_moves = ordering.order(
-            self.transposition_table.get_stored_move(zobrist_key),
-            &moves,
-            board,
-            movegen.enemy_attack_map,
-            movegen.enemy_pawn_attack_map,
-            depth,
-            false,
-        );
+        // Moves searched without causing a cutoff, which are penalised in the history tables
+        let mut quiets_tried = [Move::NULL; Self::MAX_TRIED_MOVES];
+        let mut n_quiets_tried = 0;
+        let mut captures_tried = [Move::NULL; Self::MAX_TRIED_MOVES];
+        let mut n_captures_tried = 0;
+
+        let mut n_moves = 0;
+        while let Some(m) = picker.next(board, ordering, movegen) {
+            let i = n_moves;
+            n_moves += 1;
+
+            if m == excluded_move {
+                continue;
+            }
 
-        for (i, m) in ordered_moves.into_iter().enumerate() {
             let captured_ptype = board.square[m.target()].piece_type();
             let is_capture = captured_ptype != Piece::NONE;
+            let moved_piece = board.square[m.start()];
+            let seventh_rank = if board.white_to_move { 6 } else { 1 };
+            let is_push_to_seventh = moved_piece.piece_type() == Piece::PAWN && m.target().rank() == seventh_rank;
+            let history = if is_capture { 0 } else { ordering.quiet_history(board, m, depth) };
+            let is_killer = (depth as usize) < MoveOrdering::MAX_KILLER_MOVE_DEPTH && ordering.killers[depth as usize].matches(m);
+
+            let gives_check = board.gives_check(m);
+
+            // Futility and move count pruning: near the leaves, a quiet move is unlikely to raise a
+            // static evaluation this far below alpha, and late quiet moves are unlikely to be best
+            // at all. Only done once a move has been found that doesn't get us mated. Pruned moves
+            // are never made.
+            if let Some(static_eval) = pruning_eval {
+                if !is_capture && !m.is_promotion() && !gives_check
+                && best_score > -(Self::IMMEDIATE_MATE_SCORE - 1000) {
+                    let futile = self.opts.futility
+                        && depth_remaining <= Self::FUTILITY_MAX_DEPTH
+                        && static_eval + Self::FUTILITY_MARGIN * (depth_remaining as i32) <= alpha;
+                    let late = self.opts.move_count_pruning
+                        && depth_remaining <= Self::MOVE_COUNT_PRUNING_MAX_DEPTH
+                        && i >= Self::MOVE_COUNT_PRUNING_BASE + Self::MOVE_COUNT_PRUNING_FACTOR * (depth_remaining as usize).pow(2);
+
+                    if futile || late {
+                        let reason = if futile { "futility" } else { "move count" };
+                        self.trace(|t| t.pruned_move(depth + 1, m, depth_remaining - 1, reason));
+                        continue;
+                    }
+                }
+            }
 
+            ordering.record_move(depth, board, m);
             board.make_move(m, true, zobrist);
 
-            // If the move is a check, extend the search depth
+            // Extend checks, singular moves, pawns pushed to the seventh rank (which are always
+            // passed) and, on the PV, recaptures
+            let is_recapture = is_pv && is_capture && prev_move_was_capture && m.target() == prev_move.target();
             let extension = if n_extensions < Self::MAX_EXTENSIONS {
-                if board.in_check() { 1 } else { 0 }
+                if gives_check || m == singular_move || is_push_to_seventh || is_recapture { 1 } else { 0 }
             } else { 0 };
+            count_stat!(self.extensions, extension);
 
-            let mut eval = 0;
-            let mut needs_full_search = true;
-
-            // Late move reductions: reduce search depth when searching later moves because they
-            // are likely to be bad.
-            if extension == 0 && depth_remaining >= 3 && i >= 3 && !is_capture {
-                eval = -self.search(depth + 1, depth_remaining - 2, -alpha - 1, -alpha, n_extensions, board, ordering, repetition_table, m, is_capture, zobrist, movegen);
-                needs_full_search = eval > alpha;
-            }
+            let mut eval;
 
-            if needs_full_search {
+            // Principal variation search, see `search_root`
+            if i == 0 {
                 eval = -self.search(depth + 1, depth_remaining - 1 + extension, -beta, -alpha, n_extensions + extension, board, ordering, repetition_table, m, is_capture, zobrist, movegen);
+            } else {
+                // Late move reductions
+                let reduction = if extension == 0 && !is_capture && !m.is_promotion() && depth_remaining >= Self::LMR_MIN_DEPTH {
+                    self.late_move_reduction(depth_remaining, i, history, is_killer, is_pv, improving)
+                } else {
+                    0
+                };
+
+                eval = Self::NEGATIVE_INFINITY;
+                if reduction > 0 {
+                    eval = -self.search(depth + 1, depth_remaining - 1 - reduction, -alpha - 1, -alpha, n_extensions, board, ordering, repetition_table, m, is_capture, zobrist, movegen);
+                    count_stat!(self.lmr_searches);
+                    count_stat!(self.lmr_researches, eval > alpha);
+                }
+
+                if reduction == 0 || eval > alpha {
+                    eval = -self.search(depth + 1, depth_remaining - 1 + extension, -alpha - 1, -alpha, n_extensions + extension, board, ordering, repetition_table, m, is_capture, zobrist, movegen);
+                }
+
+                // Only PV nodes have room between alpha and beta for a re-search
+                if eval > alpha && eval < beta {
+                    eval = -self.search(depth + 1, depth_remaining - 1 + extension, -beta, -alpha, n_extensions + extension, board, ordering, repetition_table, m, is_capture, zobrist, movegen);
+                }
             }
 
             board.unmake_move(m, true);
@@ -339,35 +802,68 @@ impl<'a> Searcher<'a> {
                     best_move = m;
                     eval_bound = TranspositionNodeType::Exact;
 
+                    // A singular search shares its ply with the node that started it, whose line
+                    // it must not replace
+                    if excluded_move == Move::NULL {
+                        self.pv.update(depth, m);
+                    }
+
                     // Beta cutoff / Fail high
                     if eval >= beta {
-                        self.transposition_table.store(zobrist_key, depth_remaining, depth, beta, TranspositionNodeType::LowerBound, m);
+                        count_stat!(self.beta_cutoffs);
+                        count_stat!(self.first_move_cutoffs, i == 0);
+
+                        if excluded_move == Move::NULL {
+                            self.transposition_table.store(zobrist_key, depth_remaining, depth, beta, TranspositionNodeType::LowerBound, m);
+                        }
 
-                        // Update killer moves and history heuristic for move ordering
+                        // Update killer moves and history heuristics for move ordering
                         if !is_capture {
                             if (depth as usize) < MoveOrdering::MAX_KILLER_MOVE_DEPTH {
                                 ordering.killers[depth as usize].add(m);
                             }
-                            let history_score = depth_remaining as i32 * depth_remaining as i32;
-                            ordering.history[board.move_color_idx][m.start()][m.target()] += history_score;
+                            ordering.update_quiet_histories(board, depth, m, &quiets_tried[..n_quiets_tried], depth_remaining);
                         }
+                        ordering.update_capture_histories(board, m, &captures_tried[..n_captures_tried], depth_remaining);
 
                         repetition_table.pop();
                         return beta;
                     }
                 }
             }
+
+            if is_capture && n_captures_tried < Self::MAX_TRIED_MOVES {
+                captures_tried[n_captures_tried] = m;
+                n_captures_tried += 1;
+            } else if !is_capture && !m.is_promotion() && n_quiets_tried < Self::MAX_TRIED_MOVES {
+                quiets_tried[n_quiets_tried] = m;
+                n_quiets_tried += 1;
+            }
         }
 
         repetition_table.pop();
 
+        // Consider checkmate and stalemate cases
+        if n_moves == 0 {
+            return if board.in_check() { -(Self::IMMEDIATE_MATE_SCORE - depth as i32) } else { self.draw_score(depth) };
+        }
+
+        // A search with an excluded move only answers the singular test, so it isn't stored. If
+        // the excluded move was the only one, every other move trivially fails low.
+        if excluded_move != Move::NULL {
+            return if best_score == Self::NEGATIVE_INFINITY { alpha } else { best_score };
+        }
+
         // Store this evaluation in the transposition table
         self.transposition_table.store(zobrist_key, depth_remaining, depth, best_score, eval_bound, best_move);
 
         best_score
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn quiescence_search(
+        &mut self,
+        depth: u8,
         mut alpha: i32,
         mut beta: i32,
         board: &mut Board,
@@ -375,35 +871,45 @@ impl<'a> Searcher<'a> {
         zobrist: &Zobrist,
         movegen: &mut MoveGenerator,
     ) -> i32 {
-        let mut eval = Evaluation::new(board).evaluate::<White, Black>() * if board.white_to_move { 1 } else { -1 };
+        self.count_node(depth);
+        count_stat!(self.qnodes);
+
+        let stand_pat = Evaluation::new(board).evaluate::<White, Black>() * if board.white_to_move { 1 } else { -1 };
 
         // Check for beta cutoff
-        if eval >= beta {
+        if stand_pat >= beta {
             return beta;
         }
 
-        // TODO: Delta pruning
+        // Delta pruning: if even winning a queen can't bring the evaluation up to alpha, no
+        // capture will.
+        if self.opts.delta_pruning && stand_pat + Self::capture_value(Piece::QUEEN) + Self::DELTA_MARGIN < alpha {
+            return alpha;
+        }
 
-        if eval > alpha {
-            alpha = eval;
+        if stand_pat > alpha {
+            alpha = stand_pat;
         }
 
-        let moves = movegen.generate_moves(board, true);
+        let mut picker = MovePicker::captures(ordering);
 
-        // Order moves
-        let ordered_moves = ordering.order(
-            Move::NULL,
-            &moves,
-            board,
-            movegen.enemy_attack_map,
-            movegen.enemy_pawn_attack_map,
-            0,
-            true,
-        );
+        while let Some(m) = picker.next(board, ordering, movegen) {
+            // Skip captures that lose material once all exchanges on the square are played out.
+            // The picker hands these out last.
+            if self.opts.see_pruning && picker.in_bad_captures() {
+                break;
+            }
+
+            let target = board.square[m.target()];
+
+            // Delta pruning: skip captures that can't bring the evaluation up to alpha
+            if !m.is_promotion() && target.piece_type() != Piece::NONE
+            && self.opts.delta_pruning && stand_pat + Self::capture_value(target.piece_type()) + Self::DELTA_MARGIN <= alpha {
+                continue;
+            }
 
-        for (i, m) in ordered_moves.into_iter().enumerate() {
             board.make_move(m, true, zobrist);
-            eval = -Self::quiescence_search(-beta, -alpha, board, ordering, zobrist, movegen);
+            let eval = -self.quiescence_search(depth.saturating_add(1), -beta, -alpha, board, ordering, zobrist, movegen);
             board.unmake_move(m, true);
 
             // Found a new best move
@@ -420,34 +926,431 @@ impl<'a> Searcher<'a> {
         alpha
     }
 
+    /// How many plies to reduce a late quiet move by. Moves with a good history, killers, moves at
+    /// PV nodes and moves in improving positions are reduced less. The result always leaves at
+    /// least one ply to search.
+    fn late_move_reduction(&self, depth_remaining: u8, move_number: usize, history: i32, is_killer: bool, is_pv: bool, improving: bool) -> u8 {
+        let mut r = self.reductions.get(depth_remaining, move_number);
+        r -= (history / Self::LMR_HISTORY_DIVISOR).clamp(-2, 2);
+
+        if is_killer {
+            r -= 1;
+        }
+        if is_pv {
+            r -= 1;
+        }
+        if !improving {
+            r += 1;
+        }
+
+        r.clamp(0, depth_remaining as i32 - 2) as u8
+    }
+
+    /// Score of a draw for the side to move at `ply`. The side to move at the root counts a draw
+    /// as `contempt` centipawns worse than equal, so for its opponent it is that much better.
+    fn draw_score(&self, ply: u8) -> i32 {
+        let contempt = self.opts.contempt * Evaluation::PAWN_VALUE_EG / 100;
+        if ply.is_multiple_of(2) { -contempt } else { contempt }
+    }
+
+    /// Value of capturing a piece of type `ptype`, in evaluation units.
+    fn capture_value(ptype: u8) -> i32 {
+        MoveOrdering::piece_value_score(ptype) * Evaluation::PAWN_VALUE_EG / 100
+    }
+
+    fn trace(&mut self, f: impl FnOnce(&mut Tracer)) {
+        if let Some(tracer) = &mut self.tracer {
+            f(tracer);
+        }
+    }
+
+    fn count_node(&mut self, ply: u8) {
+        self.diagnostics.nodes += 1;
+        count_stat!(self.nodes);
+        self.diagnostics.seldepth = self.diagnostics.seldepth.max(ply);
+
+        if self.thread_id != 0 && self.diagnostics.nodes.is_multiple_of(Self::NODE_BATCH_SIZE) {
+            self.helper_nodes.fetch_add(Self::NODE_BATCH_SIZE, Ordering::Relaxed);
+        }
+    }
+
+    /// Sends progress to whoever started the search. Iterations report the nodes of all threads.
+    fn report(&self, mut info: SearchInfo) {
+        let Some(sender) = &self.info else {
+            return;
+        };
+
+        if let SearchInfo::Iteration(diagnostics) = &mut info {
+            diagnostics.nodes += self.helper_nodes.load(Ordering::Relaxed);
+        }
+
+        // The receiver may have been dropped by a newer search, which is fine
+        let _ = sender.send(info);
+    }
+
+    /// Starts the clock once the opponent has played the move that was pondered on.
+    fn check_ponderhit(&mut self) {
+        if self.time.pondering() && !self.pondering.load(Ordering::Relaxed) {
+            self.time.ponderhit();
+        }
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.stop.load(Ordering::Relaxed) {
+            return true;
+        }
+
+        self.check_ponderhit();
+
+        if self.time.hard_limit_reached() {
+            return true;
+        }
+
+        // The limit is on the nodes of all threads together, which only the main thread checks.
+        // Helpers stop along with it.
+        if let Some(nodes) = self.opts.nodes.filter(|_| self.thread_id == 0) {
+            if self.diagnostics.nodes + self.helper_nodes.load(Ordering::Relaxed) >= nodes {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Forgets what earlier searches learned about the game, for when the next search is of a
+    /// different game.
+    pub fn new_game(&mut self) {
+        self.ordering = None;
+        self.last_root = None;
+    }
+
     fn init(&mut self) {
         self.best_move = None;
         self.in_search = true;
-        self.start_time = Instant::now();
         self.diagnostics = SearchDiagnostics::default();
+        #[cfg(feature = "search-stats")]
+        {
+            self.stats = stats::SearchStats::default();
+        }
     }
 
-    pub fn best_move(&mut self) -> Option<Move> {
+    /// The best move found by the last search, or `None` if a search is in progress.
+    pub fn best_move(&self) -> Option<Move> {
         if self.in_search {
             None
         } else {
             self.best_move
         }
     }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::{atomic::AtomicBool, mpsc, Arc}};
+
+    use crate::{board::{moves::Move, zobrist::Zobrist, Board}, move_gen::{magics, move_generator::MoveGenerator}, precomp};
+    use super::{diagnostics::SearchInfo, handle::SearchHandle, options::SearchOptions, ordering::MoveOrdering, repetition::RepetitionTable, trace::{TraceFormat, TraceOptions, Tracer}, transpositions::TranspositionTable, Searcher};
+
+    fn search(fen: &str, opts: SearchOptions) -> Searcher {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let mut board = Board::load_position(Some(String::from(fen)), &mut zobrist);
+        let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), Arc::new(TranspositionTable::default()), 0);
+        searcher.begin_search(opts, &mut board, &zobrist, &mut MoveGenerator::default());
+        searcher
+    }
 
-    pub fn abort(&mut self) {
-        
+    #[test]
+    fn test_depth_limit() {
+        let opts = SearchOptions { movetime: None, depth: Some(3), ..Default::default() };
+        let a = search("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", opts.clone());
+        let b = search("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", opts);
+        assert_eq!(a.diagnostics.depth_searched, 3);
+        assert_eq!(a.diagnostics.nodes, b.diagnostics.nodes);
+        assert_eq!(a.best_move(), b.best_move());
     }
 
-    // TODO: Exit search here
-    pub fn force_best_move(&mut self) -> Move {
-        self.abort();
-        self.best_move.unwrap_or(self.backup_move)
+    #[test]
+    fn test_node_limit() {
+        let opts = SearchOptions { movetime: None, nodes: Some(2000), ..Default::default() };
+        let a = search("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", opts.clone());
+        let b = search("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", opts);
+        assert!(a.diagnostics.nodes <= 2000 + 256);
+        assert_eq!(a.diagnostics.nodes, b.diagnostics.nodes);
+        assert_eq!(a.best_move(), b.best_move());
+    }
+
+    #[test]
+    fn test_node_limit_threads() {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let board = Board::load_position(Some(String::from("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")), &mut zobrist);
+        let mut handle = SearchHandle::new();
+
+        // The limit covers all threads, give or take the batches the helpers haven't added yet
+        handle.begin_search(SearchOptions { movetime: None, nodes: Some(20000), threads: 4, ..Default::default() }, &board, &zobrist);
+        handle.wait();
+        assert!(handle.diagnostics.nodes <= 20000 + 4 * Searcher::NODE_BATCH_SIZE);
+    }
+
+    #[test]
+    fn test_mate_in_one() {
+        let opts = SearchOptions { movetime: None, depth: Some(3), ..Default::default() };
+        let s = search("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", opts);
+        assert_eq!(s.diagnostics.evaluation, Searcher::IMMEDIATE_MATE_SCORE - 1);
+        assert_eq!(s.diagnostics.mate_in(), 1);
+        assert_eq!(s.best_move(), Some(Move::from_start_end(3, 59)));
+    }
+
+    #[test]
+    fn test_ordering_kept_between_searches() {
+        let opts = SearchOptions { movetime: None, depth: Some(3), ..Default::default() };
+        let mut s = search("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", opts);
+        assert!(s.ordering.as_ref().is_some_and(|o| o.history.iter().flatten().flatten().any(|&h| h != 0)));
+
+        s.new_game();
+        assert!(s.ordering.is_none());
+    }
+
+    #[test]
+    fn test_ordering_reset_for_unrelated_root() {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let mut movegen = MoveGenerator::default();
+        let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), Arc::new(TranspositionTable::default()), 0);
+        let opts = SearchOptions { movetime: None, depth: Some(3), ..Default::default() };
+
+        // The history of Bc4, which no later position can play
+        let bc4 = |s: &Searcher| s.ordering.as_ref().map_or(0, |o| o.history[0][5][26]);
+
+        let mut board = Board::load_position(Some(String::from("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")), &mut zobrist);
+        searcher.begin_search(opts.clone(), &mut board, &zobrist, &mut movegen);
+        searcher.ordering.as_mut().unwrap().history[0][5][26] = 1000;
+
+        // Playing on from the root keeps what was learned
+        board.make_move(Move::from_start_end(5, 26), false, &zobrist);
+        board.make_move(Move::from_start_end(62, 45), false, &zobrist);
+        searcher.begin_search(opts.clone(), &mut board, &zobrist, &mut movegen);
+        assert_eq!(bc4(&searcher), 500);
+
+        // A position loaded without moves is at the same ply as the first root, but isn't related
+        // to either of them
+        let mut board = Board::load_position(Some(String::from("8/8/4k3/8/2R5/4K3/3P4/6r1 w - - 0 1")), &mut zobrist);
+        searcher.begin_search(opts, &mut board, &zobrist, &mut movegen);
+        assert_eq!(bc4(&searcher), 0);
+    }
+
+    #[test]
+    fn test_partial_iteration_depth() {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let mut board = Board::load_position(Some(String::from("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")), &mut zobrist);
+        let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), Arc::new(TranspositionTable::default()), 0);
+        let (sender, receiver) = mpsc::channel();
+        searcher.info = Some(sender);
+
+        // The limit runs out during the sixth iteration, after its first root move was searched
+        let opts = SearchOptions { movetime: None, nodes: Some(7300), ..Default::default() };
+        searcher.begin_search(opts, &mut board, &zobrist, &mut MoveGenerator::default());
+
+        // The result of the iteration that was stopped is reported with its own depth, not that
+        // of the last completed iteration
+        let depths: Vec<u8> = receiver.try_iter().filter_map(|info| match info {
+            SearchInfo::Iteration(diag) => Some(diag.depth_searched),
+            _ => None,
+        }).collect();
+        assert_eq!(depths, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(searcher.diagnostics.depth_searched, 6);
+    }
+
+    #[test]
+    fn test_null_move_below_verification() {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let mut board = Board::load_position(Some(String::from("8/8/4k3/8/2R5/4K3/3P4/6r1 w - - 0 1")), &mut zobrist);
+        let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), Arc::new(TranspositionTable::default()), 0);
+        searcher.opts = SearchOptions { movetime: None, reverse_futility: false, ..Default::default() };
+        searcher.in_search = true;
+        searcher.tracer = Some(Tracer::new(TraceOptions { path: PathBuf::new(), format: TraceFormat::Json, max_ply: u8::MAX, max_nodes: usize::MAX }));
+
+        // Verifying a cutoff at the root, with a window low enough for null moves to be tried
+        searcher.null_move_verifications[0] = true;
+        let mut ordering = MoveOrdering::new();
+        let mut repetition_table = RepetitionTable::new(&board);
+        searcher.search(0, 5, -2001, -2000, 0, &mut board, &mut ordering, &mut repetition_table, Move::from_start_end(52, 44), false, &zobrist, &mut MoveGenerator::default());
+
+        // Only the node being verified skips the null move
+        let nodes = searcher.tracer.as_ref().unwrap().nodes();
+        assert!(!nodes.iter().any(|n| n.m == "null" && n.ply == 1));
+        assert!(nodes.iter().any(|n| n.m == "null" && n.ply > 1));
+    }
+
+    #[test]
+    fn test_singular_search_pv() {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let mut board = Board::load_position(Some(String::from("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")), &mut zobrist);
+        let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), Arc::new(TranspositionTable::default()), 0);
+        searcher.opts = SearchOptions { movetime: None, ..Default::default() };
+        searcher.in_search = true;
+
+        // Searching the root without one of its moves, as the singular test does, leaves the
+        // root's line alone
+        searcher.excluded_moves[0] = Move::from_start_end(5, 26);
+        let mut ordering = MoveOrdering::new();
+        let mut repetition_table = RepetitionTable::new(&board);
+        let eval = searcher.search(0, 3, -1000, 1000, 0, &mut board, &mut ordering, &mut repetition_table, Move::from_start_end(57, 42), false, &zobrist, &mut MoveGenerator::default());
+        assert!(eval > -1000 && eval < 1000);
+        assert!(searcher.pv.line().is_empty());
+    }
+
+    #[test]
+    fn test_principal_variation() {
+        let opts = SearchOptions { movetime: None, depth: Some(4), ..Default::default() };
+        let s = search("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", opts);
+        // PV nodes are never cut off by the transposition table, so the line reaches the full depth
+        assert_eq!(s.diagnostics.pv.len(), 4);
+        assert_eq!(s.diagnostics.pv.moves().first().copied(), s.best_move());
+    }
+
+    #[test]
+    fn test_multipv() {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let mut board = Board::load_position(Some(String::from("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")), &mut zobrist);
+        let mut searcher = Searcher::new(Arc::new(AtomicBool::new(false)), Arc::new(TranspositionTable::default()), 0);
+        let (sender, receiver) = mpsc::channel();
+        searcher.info = Some(sender);
+
+        let opts = SearchOptions { movetime: None, depth: Some(4), multipv: 3, ..Default::default() };
+        searcher.begin_search(opts, &mut board, &zobrist, &mut MoveGenerator::default());
+
+        // The lines of the last iteration are ranked, start with different moves and the first
+        // one has the best move
+        let lines: Vec<_> = receiver.try_iter().filter_map(|info| match info {
+            SearchInfo::Iteration(diag) if diag.depth_searched == 4 => Some(diag),
+            _ => None,
+        }).collect();
+        assert_eq!(lines.iter().map(|d| d.multipv).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(lines.windows(2).all(|w| w[0].evaluation >= w[1].evaluation));
+        assert_eq!(lines[0].pv.moves()[0], searcher.best_move().unwrap());
+        assert_ne!(lines[0].pv.moves()[0], lines[1].pv.moves()[0]);
+        assert_ne!(lines[1].pv.moves()[0], lines[2].pv.moves()[0]);
+        assert_ne!(lines[0].pv.moves()[0], lines[2].pv.moves()[0]);
+    }
+
+    #[test]
+    fn test_root_move_filter() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let a3 = Move::from_start_end(8, 16);
+        let h3 = Move::from_start_end(15, 23);
+
+        let opts = SearchOptions { movetime: None, depth: Some(3), search_moves: Some(vec![a3, h3]), ..Default::default() };
+        let best = search(fen, opts).best_move().unwrap();
+        assert!(best == a3 || best == h3);
+
+        let opts = SearchOptions { movetime: None, depth: Some(3), ..Default::default() };
+        let best = search(fen, opts.clone()).best_move().unwrap();
+        let opts = SearchOptions { excluded_moves: vec![best], ..opts };
+        assert_ne!(search(fen, opts).best_move().unwrap(), best);
+    }
+
+    #[test]
+    fn test_contempt() {
+        // Every move but Rh8+ runs into the fifty move rule, and Rh8+ doesn't mate
+        let fen = "k7/8/8/8/8/8/8/K6R w - - 99 80";
+        for contempt in [0, 100, -100] {
+            let opts = SearchOptions { movetime: None, depth: Some(3), contempt, ..Default::default() };
+            assert_eq!(search(fen, opts).diagnostics.centipawns(), -contempt);
+        }
+
+        // Contempt is relative to the side to move at the root, not to white
+        let opts = SearchOptions { movetime: None, depth: Some(3), contempt: 100, ..Default::default() };
+        assert_eq!(search("K7/8/8/8/8/8/8/k6r b - - 99 80", opts).diagnostics.centipawns(), -100);
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let a = search(fen, SearchOptions::deterministic(3000));
+
+        // A transposition table left over from another search must not change the result
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let mut board = Board::load_position(Some(String::from(fen)), &mut zobrist);
+        let mut b = Searcher::new(Arc::new(AtomicBool::new(false)), Arc::new(TranspositionTable::default()), 0);
+        let opts = SearchOptions { movetime: None, depth: Some(4), ..Default::default() };
+        b.begin_search(opts, &mut board, &zobrist, &mut MoveGenerator::default());
+        b.begin_search(SearchOptions::deterministic(3000), &mut board, &zobrist, &mut MoveGenerator::default());
+
+        assert_eq!(a.diagnostics.nodes, b.diagnostics.nodes);
+        assert_eq!(a.diagnostics.evaluation, b.diagnostics.evaluation);
+        assert_eq!(a.diagnostics.pv.moves(), b.diagnostics.pv.moves());
+    }
+
+    /// Best moves and scores of deterministic searches, which change whenever the search or the
+    /// evaluation does. Update them when that is intended.
+    #[test]
+    fn test_deterministic_suite() {
+        let suite = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", (12, 20), 27),
+            ("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", (1, 18), 146),
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", (12, 40), -52),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", (25, 29), 100),
+            ("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", (3, 59), Searcher::IMMEDIATE_MATE_SCORE - 1),
+        ];
+
+        for (fen, (start, target), score) in suite {
+            let s = search(fen, SearchOptions::deterministic(4000));
+            assert_eq!(s.best_move(), Some(Move::from_start_end(start, target)), "{}", fen);
+            assert_eq!(s.diagnostics.evaluation, score, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_null_move_zugzwang() {
+        // White has to play Rf1, after which black is in zugzwang and has to give up the pawns
+        let opts = SearchOptions { movetime: None, depth: Some(6), ..Default::default() };
+        let s = search("8/8/p1p5/1p5p/1P5p/8/PPP2K1p/4R1rk w - - 0 1", opts);
+        assert_eq!(s.best_move(), Some(Move::from_start_end(4, 5)));
+    }
+
+    #[test]
+    fn test_pruning_toggles() {
+        let off = SearchOptions {
+            movetime: None,
+            depth: Some(4),
+            null_move: false,
+            reverse_futility: false,
+            razoring: false,
+            futility: false,
+            move_count_pruning: false,
+            delta_pruning: false,
+            see_pruning: false,
+            ..Default::default()
+        };
+        let on = SearchOptions { movetime: None, depth: Some(4), ..Default::default() };
+        let a = search("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", off);
+        let b = search("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", on);
+        assert!(a.diagnostics.is_mate_score());
+        assert_eq!(a.best_move(), b.best_move());
+        assert_eq!(a.diagnostics.evaluation, b.diagnostics.evaluation);
     }
-}
 
-impl<'a> Default for Searcher<'a> {
-    fn default() -> Self {
-        Self::new()
+    #[test]
+    fn test_mate_limit() {
+        let opts = SearchOptions { movetime: None, mate: Some(2), ..Default::default() };
+        let s = search("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", opts);
+        assert!(s.diagnostics.is_mate_score());
+        assert!(s.diagnostics.depth_searched <= 3);
     }
 }
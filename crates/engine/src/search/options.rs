@@ -1,9 +1,79 @@
-#[derive(Clone, Copy)]
+use crate::board::moves::Move;
+
+use super::trace::TraceOptions;
+
+#[derive(Clone)]
 pub struct SearchOptions {
     /// Movetime in milliseconds
     pub movetime: Option<u32>,
     /// Depth to search to
     pub depth: Option<u16>,
+    /// Maximum number of nodes to search, counting every thread
+    pub nodes: Option<u64>,
+    /// Look only for a forced mate in at most this many moves instead of searching normally
+    pub mate: Option<u16>,
+    /// Only try checking moves for the mating side when looking for a forced mate
+    pub mate_checks_only: bool,
+    /// Time left on white's clock in milliseconds
+    pub wtime: Option<u32>,
+    /// Time left on black's clock in milliseconds
+    pub btime: Option<u32>,
+    /// White's increment per move in milliseconds
+    pub winc: Option<u32>,
+    /// Black's increment per move in milliseconds
+    pub binc: Option<u32>,
+    /// Moves left until the next time control
+    pub movestogo: Option<u32>,
+    /// Search on the opponent's time, ignoring the time limits until a ponderhit starts the clock
+    pub ponder: bool,
+    /// Number of threads to search with
+    pub threads: usize,
+    /// Search exactly the same way every time the same position is searched with the same
+    /// options: the clock and movetime are ignored, only one thread is used and the search starts
+    /// from an empty transposition table and move ordering tables. Bound the search with `nodes`
+    /// or `depth` instead.
+    pub deterministic: bool,
+    /// Write the tree explored by the main thread to a file
+    pub trace: Option<TraceOptions>,
+    /// Number of best root moves to search and report lines for
+    pub multipv: usize,
+    /// Only search these root moves
+    pub search_moves: Option<Vec<Move>>,
+    /// Never search these root moves
+    pub excluded_moves: Vec<Move>,
+    /// How much worse than equal a draw is for the side to move at the root, in centipawns.
+    /// Negative values make the engine seek draws.
+    pub contempt: i32,
+    /// Prune nodes where passing the turn still fails high
+    pub null_move: bool,
+    /// Prune shallow nodes whose static evaluation is far above beta
+    pub reverse_futility: bool,
+    /// Drop into quiescence search at shallow nodes whose static evaluation is far below alpha
+    pub razoring: bool,
+    /// Prune quiet moves at frontier nodes whose static evaluation is far below alpha
+    pub futility: bool,
+    /// Prune late quiet moves at frontier nodes
+    pub move_count_pruning: bool,
+    /// Prune captures in quiescence search that can't bring the evaluation up to alpha
+    pub delta_pruning: bool,
+    /// Prune captures in quiescence search that lose material by static exchange evaluation
+    pub see_pruning: bool,
+    /// Constant term of the late move reduction table
+    pub lmr_base: f32,
+    /// Divisor of the logarithmic term of the late move reduction table
+    pub lmr_divisor: f32,
+}
+
+impl SearchOptions {
+    /// Options for a reproducible search of `nodes` nodes. See [`SearchOptions::deterministic`].
+    pub fn deterministic(nodes: u64) -> Self {
+        Self {
+            movetime: None,
+            nodes: Some(nodes),
+            deterministic: true,
+            ..Default::default()
+        }
+    }
 }
 
 impl Default for SearchOptions {
@@ -11,6 +81,31 @@ impl Default for SearchOptions {
         Self {
             movetime: Some(1000),
             depth: None,
+            nodes: None,
+            mate: None,
+            mate_checks_only: false,
+            wtime: None,
+            btime: None,
+            winc: None,
+            binc: None,
+            movestogo: None,
+            ponder: false,
+            threads: 1,
+            deterministic: false,
+            trace: None,
+            multipv: 1,
+            search_moves: None,
+            excluded_moves: Vec::new(),
+            contempt: 0,
+            null_move: true,
+            reverse_futility: true,
+            razoring: true,
+            futility: true,
+            move_count_pruning: true,
+            delta_pruning: true,
+            see_pruning: true,
+            lmr_base: 0.75,
+            lmr_divisor: 2.25,
         }
     }
 }
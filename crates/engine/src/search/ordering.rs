@@ -1,12 +1,27 @@
 use crate::{board::{coord::Coord, moves::Move, piece::Piece, Board}, precomp::Precomputed, prelude::BitBoard};
 
+use super::pv::PvTable;
+
 pub struct MoveOrdering {
     pub killers: [KillerMoves; Self::MAX_KILLER_MOVE_DEPTH],
+    /// History of quiet moves by side to move, start and target square
     pub history: [[[i32; 64]; 64]; 2],
+    /// The quiet move that last refuted a move, by the piece that made it and its target square
+    pub counter_moves: [[Move; 64]; Self::PIECES],
+    /// History of quiet moves following the moves one and two plies back
+    pub continuation_history: [ContinuationHistory; 2],
+    /// History of captures by moving piece, target square and captured piece type
+    pub capture_history: [[[i32; 8]; 64]; Self::PIECES],
+    /// The move played at each ply of the current line
+    played: [Option<PieceTo>; PvTable::MAX_PLY],
 }
 
 impl MoveOrdering {
     pub const MAX_KILLER_MOVE_DEPTH: usize = 32;
+    /// Upper bound on the magnitude of every history entry
+    pub const MAX_HISTORY: i32 = 16384;
+    const PIECES: usize = Piece::MAX_PIECE_INDEX as usize + 1;
+    const CAPTURE_HISTORY_DIVISOR: i32 = 16;
     
     const CONTROLLED_BY_OPP_PAWN_PENALTY: i32 = 350;
     const CAPTURED_PIECE_MULTIPLIER: i32 = 100;
@@ -17,13 +32,163 @@ impl MoveOrdering {
     const PROMOTION_BIAS: i32 = 6 * Self::MILLION;
     const LOSING_CAPTURE_BIAS: i32 = 2 * Self::MILLION;
     const KILLER_BIAS: i32 = 4 * Self::MILLION;
+    const COUNTER_MOVE_BIAS: i32 = 3 * Self::MILLION;
     const NORMAL_BIAS: i32 = 0;
 
     pub fn new() -> Self {
         Self {
             killers: [KillerMoves::default(); Self::MAX_KILLER_MOVE_DEPTH],
             history: [[[0; 64]; 64]; 2],
+            counter_moves: [[Move::NULL; 64]; Self::PIECES],
+            continuation_history: [ContinuationHistory::new(), ContinuationHistory::new()],
+            capture_history: [[[0; 8]; 64]; Self::PIECES],
+            played: [None; PvTable::MAX_PLY],
+        }
+    }
+
+    /// Prepares the tables of the last search for a search of a position `plies_advanced` plies
+    /// further into the same game, or of an unrelated position if `None`.
+    ///
+    /// Histories are halved so that they still guide the first iterations without outweighing
+    /// what the new search learns. Killers move down by the plies played, so they stay at the ply
+    /// of the positions they were found in, and counter-moves are kept as they are.
+    pub fn age(&mut self, plies_advanced: Option<usize>) {
+        for entry in self.history.iter_mut().flatten().flatten() {
+            *entry /= 2;
+        }
+        for table in self.continuation_history.iter_mut() {
+            table.age();
+        }
+        for entry in self.capture_history.iter_mut().flatten().flatten() {
+            *entry /= 2;
+        }
+
+        let shift = plies_advanced.unwrap_or(Self::MAX_KILLER_MOVE_DEPTH).min(Self::MAX_KILLER_MOVE_DEPTH);
+        self.killers.rotate_left(shift);
+        for killers in self.killers[Self::MAX_KILLER_MOVE_DEPTH - shift..].iter_mut() {
+            *killers = KillerMoves::default();
+        }
+
+        self.played = [None; PvTable::MAX_PLY];
+    }
+
+    /// Remembers that `m` is played at `ply`. Must be called before the move is made, with
+    /// [`Move::NULL`] for null moves.
+    pub fn record_move(&mut self, ply: u8, board: &Board, m: Move) {
+        if let Some(played) = self.played.get_mut(ply as usize) {
+            *played = if m == Move::NULL { None } else { Some(PieceTo::new(board, m)) };
+        }
+    }
+
+    /// The move played `plies_back` plies before `ply`, if it wasn't a null move.
+    fn previous(&self, ply: u8, plies_back: usize) -> Option<PieceTo> {
+        (ply as usize).checked_sub(plies_back).and_then(|i| self.played.get(i).copied().flatten())
+    }
+
+    /// The move that last refuted the previous move at `ply`.
+    pub fn counter_move(&self, ply: u8) -> Move {
+        self.previous(ply, 1).map_or(Move::NULL, |prev| self.counter_moves[prev.piece][prev.to])
+    }
+
+    /// Combined butterfly and continuation history of the quiet move `m` at `ply`.
+    pub fn quiet_history(&self, board: &Board, m: Move, ply: u8) -> i32 {
+        let current = PieceTo::new(board, m);
+        let mut score = self.history[board.move_color_idx][m.start()][m.target()];
+
+        for (plies_back, table) in self.continuation_history.iter().enumerate() {
+            if let Some(prev) = self.previous(ply, plies_back + 1) {
+                score += table.get(prev, current);
+            }
         }
+
+        score
+    }
+
+    /// Score of a quiet move (or underpromotion) used by the move picker: its history, and how
+    /// much better the square the piece moves to is, unless it is attacked.
+    pub fn quiet_score(&self, board: &Board, m: Move, ply: u8, opp_attacks: BitBoard, opp_pawn_attacks: BitBoard) -> i32 {
+        let piece = board.square[m.start()];
+        let mut score = self.quiet_history(board, m, ply);
+
+        if piece.is_not_pawn_king() {
+            score += Self::psqt_score(piece, m.target()) - Self::psqt_score(piece, m.start());
+
+            if opp_pawn_attacks.contains_square(m.target().square()) {
+                score -= 50;
+            } else if opp_attacks.contains_square(m.target().square()) {
+                score -= 25;
+            }
+        }
+
+        score
+    }
+
+    /// Score of a capture (or queen promotion) used by the move picker: most valuable victim
+    /// first, then least valuable attacker, adjusted by capture history.
+    pub fn capture_score(&self, board: &Board, m: Move) -> i32 {
+        let victim = if m.move_flag() == Move::EN_PASSANT_CAPTURE {
+            Piece::PAWN
+        } else {
+            board.square[m.target()].piece_type()
+        };
+        let mut score = Self::piece_value_score(victim) * Self::CAPTURED_PIECE_MULTIPLIER / 10
+            - Self::piece_value_score(board.square[m.start()].piece_type()) / 10;
+
+        if m.move_flag() == Move::QUEEN_PROMOTION {
+            score += Self::piece_value_score(Piece::QUEEN) * Self::CAPTURED_PIECE_MULTIPLIER / 10;
+        }
+
+        score + self.capture_history_entry(board, m) / Self::CAPTURE_HISTORY_DIVISOR
+    }
+
+    fn capture_history_entry(&self, board: &Board, m: Move) -> i32 {
+        let current = PieceTo::new(board, m);
+        self.capture_history[current.piece][current.to][board.square[m.target()].piece_type() as usize]
+    }
+
+    /// Rewards the quiet move `best` that caused a beta cutoff at `ply` and penalises the quiet
+    /// moves searched before it, which didn't.
+    pub fn update_quiet_histories(&mut self, board: &Board, ply: u8, best: Move, tried: &[Move], depth_remaining: u8) {
+        let bonus = Self::history_bonus(depth_remaining);
+
+        if let Some(prev) = self.previous(ply, 1) {
+            self.counter_moves[prev.piece][prev.to] = best;
+        }
+
+        for (m, bonus) in tried.iter().map(|m| (*m, -bonus)).chain(std::iter::once((best, bonus))) {
+            let current = PieceTo::new(board, m);
+            Self::apply_bonus(&mut self.history[board.move_color_idx][m.start()][m.target()], bonus);
+
+            for plies_back in 0..self.continuation_history.len() {
+                if let Some(prev) = self.previous(ply, plies_back + 1) {
+                    Self::apply_bonus(self.continuation_history[plies_back].get_mut(prev, current), bonus);
+                }
+            }
+        }
+    }
+
+    /// Rewards `best` if it is a capture that caused a beta cutoff, and penalises the captures
+    /// searched before it, which didn't.
+    pub fn update_capture_histories(&mut self, board: &Board, best: Move, tried: &[Move], depth_remaining: u8) {
+        let bonus = Self::history_bonus(depth_remaining);
+        let best = Some(best).filter(|m| board.square[m.target()].piece_type() != Piece::NONE);
+
+        for (m, bonus) in tried.iter().map(|m| (*m, -bonus)).chain(best.map(|m| (m, bonus))) {
+            let current = PieceTo::new(board, m);
+            let captured = board.square[m.target()].piece_type() as usize;
+            Self::apply_bonus(&mut self.capture_history[current.piece][current.to][captured], bonus);
+        }
+    }
+
+    /// History bonus for a cutoff with `depth_remaining`. Deeper cutoffs are more informative.
+    fn history_bonus(depth_remaining: u8) -> i32 {
+        (depth_remaining as i32 * depth_remaining as i32 * 16).min(Self::MAX_HISTORY / 8)
+    }
+
+    /// History gravity: the closer an entry is to [`Self::MAX_HISTORY`], the less a bonus of the
+    /// same sign moves it, so entries stay bounded and old results decay as new ones come in.
+    fn apply_bonus(entry: &mut i32, bonus: i32) {
+        *entry += bonus - *entry * bonus.abs() / Self::MAX_HISTORY;
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -40,6 +205,7 @@ impl MoveOrdering {
         let opps = board.enemy_diagonal_sliders | board.enemy_orthogonal_sliders 
             | board.piece_bitboards[Piece::new(Piece::KNIGHT | board.opponent_color)];
         let mut scores = Vec::with_capacity(moves.len());
+        let counter_move = if in_q_search { Move::NULL } else { self.counter_move(depth) };
 
         for m in moves {
             if *m == firstmove {
@@ -67,6 +233,8 @@ impl MoveOrdering {
                 } else {
                     score += Self::WINNING_CAPTURE_BIAS + material_diff;
                 }
+
+                score += self.capture_history_entry(board, *m) / Self::CAPTURE_HISTORY_DIVISOR;
             }
 
             if move_ptype == Piece::PAWN {
@@ -87,8 +255,18 @@ impl MoveOrdering {
 
             if !is_capture {
                 let is_killer = !in_q_search && depth < Self::MAX_KILLER_MOVE_DEPTH as u8 && self.killers[depth as usize].matches(*m);
-                score += if is_killer { Self::KILLER_BIAS } else { Self::NORMAL_BIAS };
-                score += self.history[board.move_color_idx][m.start()][m.target()];
+                score += if is_killer {
+                    Self::KILLER_BIAS
+                } else if *m == counter_move {
+                    Self::COUNTER_MOVE_BIAS
+                } else {
+                    Self::NORMAL_BIAS
+                };
+                score += if in_q_search {
+                    self.history[board.move_color_idx][m.start()][m.target()]
+                } else {
+                    self.quiet_history(board, *m, depth)
+                };
             }
 
             scores.push(score);
@@ -203,6 +381,65 @@ impl Default for MoveOrdering {
 }
 
 
+/// A move identified by the piece that made it and its target square.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PieceTo {
+    piece: usize,
+    to: usize,
+}
+
+impl PieceTo {
+    /// Must be called before `m` is made on `board`.
+    fn new(board: &Board, m: Move) -> Self {
+        Self {
+            piece: board.square[m.start()].index(),
+            to: m.target().square() as usize,
+        }
+    }
+}
+
+
+/// History of quiet moves indexed by a previous move and the move itself, both as piece and target
+/// square.
+pub struct ContinuationHistory {
+    table: Vec<i32>,
+}
+
+impl ContinuationHistory {
+    const SIZE: usize = MoveOrdering::PIECES * 64;
+
+    pub fn new() -> Self {
+        Self {
+            table: vec![0; Self::SIZE * Self::SIZE],
+        }
+    }
+
+    fn index(prev: PieceTo, current: PieceTo) -> usize {
+        (prev.piece * 64 + prev.to) * Self::SIZE + current.piece * 64 + current.to
+    }
+
+    fn age(&mut self) {
+        for entry in self.table.iter_mut() {
+            *entry /= 2;
+        }
+    }
+
+    fn get(&self, prev: PieceTo, current: PieceTo) -> i32 {
+        self.table[Self::index(prev, current)]
+    }
+
+    fn get_mut(&mut self, prev: PieceTo, current: PieceTo) -> &mut i32 {
+        &mut self.table[Self::index(prev, current)]
+    }
+}
+
+impl Default for ContinuationHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
 #[derive(Clone, Copy)]
 pub struct KillerMoves {
     pub a: Move,
@@ -230,3 +467,73 @@ impl Default for KillerMoves {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::{moves::Move, zobrist::Zobrist, Board}, move_gen::magics, precomp};
+    use super::MoveOrdering;
+
+    fn board(fen: &str) -> Board {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        Board::load_position(Some(fen.to_string()), &mut zobrist)
+    }
+
+    #[test]
+    fn test_history_gravity() {
+        let board = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let mut ordering = MoveOrdering::new();
+        let good = Move::from_start_end(6, 21);
+        let bad = Move::from_start_end(1, 18);
+
+        for _ in 0..1000 {
+            ordering.update_quiet_histories(&board, 0, good, &[bad], 20);
+        }
+
+        assert!(ordering.quiet_history(&board, good, 0) <= MoveOrdering::MAX_HISTORY);
+        assert!(ordering.quiet_history(&board, good, 0) > MoveOrdering::MAX_HISTORY / 2);
+        assert!(ordering.quiet_history(&board, bad, 0) >= -MoveOrdering::MAX_HISTORY);
+        assert!(ordering.quiet_history(&board, bad, 0) < -MoveOrdering::MAX_HISTORY / 2);
+    }
+
+    #[test]
+    fn test_counter_move() {
+        let board = board("rnbqkbnr/pppppppp/8/8/8/5N2/PPPPPPPP/RNBQKB1R b KQkq - 1 1");
+        let mut ordering = MoveOrdering::new();
+        let prev = Move::from_start_end(6, 21);
+        let counter = Move::from_start_end(57, 42);
+
+        // The previous move is recorded on the position before it was made
+        let before = self::board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        ordering.record_move(0, &before, prev);
+        ordering.update_quiet_histories(&board, 1, counter, &[], 4);
+        assert_eq!(ordering.counter_move(1), counter);
+
+        // Continuation history now prefers the counter-move after the same previous move
+        let moves = [Move::from_start_end(62, 45), counter];
+        let ordered = ordering.order(Move::NULL, &moves, &board, Default::default(), Default::default(), 1, false);
+        assert_eq!(ordered[0], counter);
+    }
+
+    #[test]
+    fn test_age() {
+        let board = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let mut ordering = MoveOrdering::new();
+        let good = Move::from_start_end(6, 21);
+        let killer = Move::from_start_end(12, 28);
+        ordering.update_quiet_histories(&board, 0, good, &[], 8);
+        ordering.killers[2].add(killer);
+        let history = ordering.quiet_history(&board, good, 0);
+
+        // Two plies later, the killer of ply 2 belongs to the new root
+        ordering.age(Some(2));
+        assert_eq!(ordering.quiet_history(&board, good, 0), history / 2);
+        assert!(ordering.killers[0].matches(killer));
+        assert!(!ordering.killers[2].matches(killer));
+
+        ordering.age(None);
+        assert!(ordering.killers.iter().all(|k| !k.matches(killer)));
+    }
+}
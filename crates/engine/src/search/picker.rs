@@ -0,0 +1,303 @@
+use crate::{board::{moves::Move, piece::Piece, Board}, move_gen::{move_generator::MoveGenerator, move_list::MoveList}};
+
+use super::{ordering::MoveOrdering, see::static_exchange_eval};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    TtMove,
+    GenerateCaptures,
+    GoodCaptures,
+    FirstKiller,
+    SecondKiller,
+    CounterMove,
+    GenerateQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+/// Hands out the legal moves of a position one at a time, in the order they should be searched:
+/// the transposition table move, captures that don't lose material, killers, the counter-move,
+/// quiet moves by history and finally captures that lose material.
+///
+/// Moves are generated and scored only when their stage is reached, so a cutoff by an early move
+/// saves the rest of the work. Everything is kept on the stack.
+pub struct MovePicker {
+    stage: Stage,
+    ply: u8,
+    tt_move: Move,
+    killers: [Move; 2],
+    counter_move: Move,
+    captures: ScoredMoves,
+    quiets: ScoredMoves,
+    captures_only: bool,
+}
+
+impl MovePicker {
+    /// Score above which captures are searched before quiet moves
+    const GOOD_CAPTURE_SCORE: i32 = 1_000_000;
+
+    /// Picks every legal move at `ply`, starting with `tt_move` if it is legal.
+    pub fn new(tt_move: Move, ply: u8, ordering: &MoveOrdering) -> Self {
+        let killers = ordering.killers.get(ply as usize).map_or([Move::NULL; 2], |k| [k.a, k.b]);
+
+        Self {
+            stage: Stage::TtMove,
+            ply,
+            tt_move,
+            killers,
+            counter_move: ordering.counter_move(ply),
+            captures: ScoredMoves::new(),
+            quiets: ScoredMoves::new(),
+            captures_only: false,
+        }
+    }
+
+    /// Picks only captures and queen promotions, as in quiescence search.
+    pub fn captures(ordering: &MoveOrdering) -> Self {
+        Self {
+            stage: Stage::GenerateCaptures,
+            captures_only: true,
+            ..Self::new(Move::NULL, 0, ordering)
+        }
+    }
+
+    /// Whether the moves handed out from now on lose material by static exchange evaluation.
+    pub fn in_bad_captures(&self) -> bool {
+        self.stage == Stage::BadCaptures
+    }
+
+    /// Whether the transposition table move is legal in this position.
+    pub fn has_tt_move(&self, board: &Board, movegen: &MoveGenerator) -> bool {
+        movegen.is_legal(board, self.tt_move)
+    }
+
+    /// The next move to search, or `None` once every legal move has been picked.
+    pub fn next(&mut self, board: &Board, ordering: &MoveOrdering, movegen: &mut MoveGenerator) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    if self.has_tt_move(board, movegen) {
+                        return Some(self.tt_move);
+                    }
+                },
+                Stage::GenerateCaptures => {
+                    self.generate_captures(board, ordering, movegen);
+                    self.stage = Stage::GoodCaptures;
+                },
+                Stage::GoodCaptures => {
+                    match self.captures.pick() {
+                        Some((m, score)) if score >= Self::GOOD_CAPTURE_SCORE => {
+                            if m != self.tt_move {
+                                return Some(m);
+                            }
+                        },
+                        Some(_) => {
+                            self.captures.unpick();
+                            self.stage = if self.captures_only { Stage::BadCaptures } else { Stage::FirstKiller };
+                        },
+                        None => {
+                            self.stage = if self.captures_only { Stage::Done } else { Stage::FirstKiller };
+                        },
+                    }
+                },
+                Stage::FirstKiller | Stage::SecondKiller | Stage::CounterMove => {
+                    let m = match self.stage {
+                        Stage::FirstKiller => self.killers[0],
+                        Stage::SecondKiller => self.killers[1],
+                        _ => self.counter_move,
+                    };
+                    self.stage = match self.stage {
+                        Stage::FirstKiller => Stage::SecondKiller,
+                        Stage::SecondKiller => Stage::CounterMove,
+                        _ => Stage::GenerateQuiets,
+                    };
+
+                    if self.is_special_quiet(m, board, movegen) {
+                        return Some(m);
+                    }
+                },
+                Stage::GenerateQuiets => {
+                    self.generate_quiets(board, ordering, movegen);
+                    self.stage = Stage::Quiets;
+                },
+                Stage::Quiets => {
+                    match self.quiets.pick() {
+                        Some((m, _)) => {
+                            if m != self.tt_move && !self.killers.contains(&m) && m != self.counter_move {
+                                return Some(m);
+                            }
+                        },
+                        None => self.stage = Stage::BadCaptures,
+                    }
+                },
+                Stage::BadCaptures => {
+                    match self.captures.pick() {
+                        Some((m, _)) => {
+                            if m != self.tt_move {
+                                return Some(m);
+                            }
+                        },
+                        None => self.stage = Stage::Done,
+                    }
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    /// Whether `m` is a legal quiet move that hasn't been handed out yet by an earlier stage.
+    fn is_special_quiet(&self, m: Move, board: &Board, movegen: &MoveGenerator) -> bool {
+        if m == Move::NULL || m == self.tt_move {
+            return false;
+        }
+        if self.stage > Stage::SecondKiller && self.killers[0] == m {
+            return false;
+        }
+        if self.stage > Stage::CounterMove && self.killers[1] == m {
+            return false;
+        }
+
+        !Self::is_capture_stage_move(board, m) && movegen.is_legal(board, m)
+    }
+
+    /// Captures, en passant and queen promotions are generated together, everything else
+    /// (including capturing underpromotions) is quiet.
+    fn is_capture_stage_move(board: &Board, m: Move) -> bool {
+        if m.is_promotion() {
+            return m.move_flag() == Move::QUEEN_PROMOTION;
+        }
+
+        board.square[m.target()].piece_type() != Piece::NONE || m.move_flag() == Move::EN_PASSANT_CAPTURE
+    }
+
+    fn generate_captures(&mut self, board: &Board, ordering: &MoveOrdering, movegen: &mut MoveGenerator) {
+        self.captures.moves.clone_from(movegen.generate_captures(board));
+        for (m, score) in self.captures.moves.iter().zip(self.captures.scores.iter_mut()) {
+            let target = board.square[m.target()];
+            let loses_material = target.piece_type() != Piece::NONE
+                && !m.is_promotion()
+                && static_exchange_eval(board, *m, target, board.square[m.start()]) < 0;
+
+            *score = ordering.capture_score(board, *m) + if loses_material { 0 } else { Self::GOOD_CAPTURE_SCORE };
+        }
+    }
+
+    fn generate_quiets(&mut self, board: &Board, ordering: &MoveOrdering, movegen: &mut MoveGenerator) {
+        self.quiets.moves.clone_from(movegen.generate_quiets(board));
+        let (opp_attacks, opp_pawn_attacks) = (movegen.enemy_attack_map, movegen.enemy_pawn_attack_map);
+        for (m, score) in self.quiets.moves.iter().zip(self.quiets.scores.iter_mut()) {
+            *score = ordering.quiet_score(board, *m, self.ply, opp_attacks, opp_pawn_attacks);
+        }
+    }
+}
+
+
+/// Moves with scores, handed out best first by selection sort.
+struct ScoredMoves {
+    moves: MoveList,
+    scores: [i32; MoveList::CAPACITY],
+    picked: usize,
+}
+
+impl ScoredMoves {
+    fn new() -> Self {
+        Self {
+            moves: MoveList::new(),
+            scores: [0; MoveList::CAPACITY],
+            picked: 0,
+        }
+    }
+
+    /// Takes the best move that hasn't been picked yet.
+    fn pick(&mut self) -> Option<(Move, i32)> {
+        let best = (self.picked..self.moves.len()).max_by_key(|&i| self.scores[i])?;
+        self.moves.swap(self.picked, best);
+        self.scores.swap(self.picked, best);
+        self.picked += 1;
+
+        Some((self.moves[self.picked - 1], self.scores[self.picked - 1]))
+    }
+
+    /// Puts back the last picked move.
+    fn unpick(&mut self) {
+        self.picked -= 1;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::{moves::Move, piece::Piece, zobrist::Zobrist, Board}, move_gen::{magics, move_generator::MoveGenerator}, precomp, search::ordering::MoveOrdering};
+    use super::MovePicker;
+
+    fn board(fen: &str) -> Board {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        Board::load_position(Some(fen.to_string()), &mut zobrist)
+    }
+
+    #[test]
+    fn test_picks_every_move_once() {
+        let board = board("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let mut movegen = MoveGenerator::default();
+        let mut ordering = MoveOrdering::new();
+        let tt_move = Move::from_start_end(12, 40);
+        let killer = Move::from_start_end(21, 29);
+        ordering.killers[3].add(killer);
+
+        let mut picker = MovePicker::new(tt_move, 3, &ordering);
+        let mut picked = Vec::new();
+        while let Some(m) = picker.next(&board, &ordering, &mut movegen) {
+            picked.push(m);
+        }
+
+        let mut all = movegen.generate_moves(&board, false);
+        assert_eq!(picked[0], tt_move);
+        assert_eq!(picked.len(), all.len());
+        picked.sort_unstable_by_key(|m| m.value());
+        all.sort_unstable_by_key(|m| m.value());
+        assert_eq!(picked, all);
+    }
+
+    #[test]
+    fn test_stage_order() {
+        // Nxb5 wins a rook, while Nxd5 and Qxd5 both lose material to exd5
+        let board = board("4k3/8/4p3/1r1p4/8/2N5/8/3QK3 w - - 0 1");
+        let mut movegen = MoveGenerator::default();
+        let mut ordering = MoveOrdering::new();
+        let killer = Move::from_start_end(4, 13);
+        ordering.killers[0].add(killer);
+
+        let mut picker = MovePicker::new(Move::NULL, 0, &ordering);
+        let mut picked = Vec::new();
+        while let Some(m) = picker.next(&board, &ordering, &mut movegen) {
+            picked.push(m);
+        }
+
+        let n = picked.len();
+        assert_eq!(picked[0], Move::from_start_end(18, 33));
+        assert_eq!(picked[1], killer);
+        assert_eq!(picked[n - 2..].iter().filter(|m| m.target() == Move::from_start_end(3, 35).target()).count(), 2);
+        assert!(picked[2..n - 2].iter().all(|m| board.square[m.target()].piece_type() == Piece::NONE));
+    }
+
+    #[test]
+    fn test_illegal_tt_move() {
+        let board = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let mut movegen = MoveGenerator::default();
+        let ordering = MoveOrdering::new();
+
+        // A move from another position must not be handed out
+        let mut picker = MovePicker::new(Move::from_start_end(3, 39), 0, &ordering);
+        assert!(!picker.has_tt_move(&board, &movegen));
+
+        let mut n = 0;
+        while picker.next(&board, &ordering, &mut movegen).is_some() {
+            n += 1;
+        }
+        assert_eq!(n, 20);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::board::moves::Move;
+
+/// The line of play the search expects from the root, best move first.
+#[derive(Clone, Copy)]
+pub struct PrincipalVariation {
+    moves: [Move; Self::MAX_LENGTH],
+    len: usize,
+}
+
+impl PrincipalVariation {
+    pub const MAX_LENGTH: usize = 64;
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Default for PrincipalVariation {
+    fn default() -> Self {
+        Self {
+            moves: [Move::NULL; Self::MAX_LENGTH],
+            len: 0,
+        }
+    }
+}
+
+impl std::fmt::Debug for PrincipalVariation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<String> = self.moves().iter().map(|m| m.name()).collect();
+        write!(f, "{}", names.join(" "))
+    }
+}
+
+
+/// Triangular table that collects the principal variation while searching.
+///
+/// Row `ply` holds the best line found so far from the node at that ply. Whenever a move raises
+/// alpha, it is prepended to the line of the child node, so that row 0 ends up holding the line
+/// from the root.
+pub struct PvTable {
+    moves: Box<[[Move; Self::MAX_PLY]; Self::MAX_PLY]>,
+    lengths: [usize; Self::MAX_PLY],
+}
+
+impl PvTable {
+    pub const MAX_PLY: usize = 128;
+
+    pub fn new() -> Self {
+        Self {
+            moves: Box::new([[Move::NULL; Self::MAX_PLY]; Self::MAX_PLY]),
+            lengths: [0; Self::MAX_PLY],
+        }
+    }
+
+    /// Empties the line at `ply`. Should be called when entering a node.
+    pub fn clear(&mut self, ply: u8) {
+        if let Some(len) = self.lengths.get_mut(ply as usize) {
+            *len = 0;
+        }
+    }
+
+    /// Sets the line at `ply` to `m` followed by the line of the child node.
+    pub fn update(&mut self, ply: u8, m: Move) {
+        let ply = ply as usize;
+        if ply + 1 >= Self::MAX_PLY {
+            return;
+        }
+
+        let child_len = self.lengths[ply + 1].min(Self::MAX_PLY - 1);
+        let (parents, children) = self.moves.split_at_mut(ply + 1);
+        parents[ply][0] = m;
+        parents[ply][1..=child_len].copy_from_slice(&children[0][..child_len]);
+        self.lengths[ply] = child_len + 1;
+    }
+
+    /// The line from the root.
+    pub fn line(&self) -> PrincipalVariation {
+        let len = self.lengths[0].min(PrincipalVariation::MAX_LENGTH);
+        let mut moves = [Move::NULL; PrincipalVariation::MAX_LENGTH];
+        moves[..len].copy_from_slice(&self.moves[0][..len]);
+        PrincipalVariation { moves, len }
+    }
+}
+
+impl Default for PvTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::board::moves::Move;
+    use super::PvTable;
+
+    #[test]
+    fn test_update() {
+        let a = Move::from_start_end(12, 28);
+        let b = Move::from_start_end(52, 36);
+        let c = Move::from_start_end(6, 21);
+        let mut pv = PvTable::new();
+
+        pv.clear(0);
+        pv.clear(1);
+        pv.clear(2);
+        pv.update(2, c);
+        pv.update(1, b);
+        pv.update(0, a);
+        assert_eq!(pv.line().moves(), &[a, b, c]);
+
+        // A new best move at the root whose child line is empty
+        pv.clear(1);
+        pv.update(0, b);
+        assert_eq!(pv.line().moves(), &[b]);
+    }
+}
@@ -0,0 +1,60 @@
+/// Late move reductions by remaining depth and move number.
+///
+/// The reduction grows with the logarithm of both, as `base + ln(depth) * ln(move_number) /
+/// divisor`, so that late moves at high depths are reduced the most.
+pub struct ReductionTable {
+    table: [[u8; Self::SIZE]; Self::SIZE],
+    base: f32,
+    divisor: f32,
+}
+
+impl ReductionTable {
+    const SIZE: usize = 64;
+
+    pub fn new(base: f32, divisor: f32) -> Self {
+        let mut table = [[0; Self::SIZE]; Self::SIZE];
+
+        for (depth, row) in table.iter_mut().enumerate().skip(1) {
+            for (move_number, r) in row.iter_mut().enumerate().skip(1) {
+                let reduction = base + (depth as f32).ln() * (move_number as f32).ln() / divisor;
+                *r = reduction.clamp(0.0, u8::MAX as f32) as u8;
+            }
+        }
+
+        Self { table, base, divisor }
+    }
+
+    /// Whether the table was built with these parameters.
+    pub fn uses(&self, base: f32, divisor: f32) -> bool {
+        self.base == base && self.divisor == divisor
+    }
+
+    /// The reduction for the `move_number`th move (counting from 0) at `depth` remaining.
+    pub fn get(&self, depth: u8, move_number: usize) -> i32 {
+        self.table[(depth as usize).min(Self::SIZE - 1)][move_number.min(Self::SIZE - 1)] as i32
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::ReductionTable;
+
+    #[test]
+    fn test_reductions() {
+        let table = ReductionTable::new(0.75, 2.25);
+        assert_eq!(table.get(3, 0), 0);
+        assert_eq!(table.get(3, 1), 0);
+        assert_eq!(table.get(3, 3), 1);
+
+        for depth in 1..64 {
+            for move_number in 1..64 {
+                assert!(table.get(depth, move_number) >= table.get(depth - 1, move_number));
+                assert!(table.get(depth, move_number) >= table.get(depth, move_number - 1));
+            }
+        }
+
+        assert!(table.uses(0.75, 2.25));
+        assert!(!table.uses(1.0, 2.25));
+    }
+}
@@ -1,53 +1,158 @@
 use crate::board::Board;
 
+/// Zobrist keys of the positions on the current line, from the game history through to the node
+/// being searched, used to detect draws by repetition.
+///
+/// Only positions since the last irreversible move (a capture, a pawn move or a null move) can
+/// repeat, so each key remembers where its window of possibly repeated positions starts.
 pub struct RepetitionTable {
-    hashes: [u64; 256],
-    start_indices: [u32; 257],
-    count: usize,
+    keys: Vec<u64>,
+    window_starts: Vec<usize>,
+    /// Number of keys that come from the game history, the last of which is the search root
+    root_len: usize,
 }
 
 impl RepetitionTable {
+    /// Creates a table from the game history of `board`. The current position is the root.
     pub fn new(board: &Board) -> Self {
-        let init_hashes = board.repeat_position_history.clone().into_iter().rev();
-        let count = init_hashes.len();
+        let keys = board.repeat_position_history.clone();
+        let root_len = keys.len();
 
-        let mut hashes = [0; 256];
-        for (hash, init) in hashes.iter_mut().zip(init_hashes) {
-            *hash = init;
+        Self {
+            window_starts: vec![0; keys.len()],
+            keys,
+            root_len,
         }
+    }
+
+    /// Adds a position reached by a move that is `irreversible` or not.
+    pub fn push(&mut self, key: u64, irreversible: bool) {
+        self.window_starts.push(self.window_start(irreversible));
+        self.keys.push(key);
+    }
 
-        RepetitionTable {
-            hashes,
-            start_indices: [0; 257],
-            count,
+    pub fn pop(&mut self) {
+        if self.keys.len() > self.root_len {
+            self.keys.pop();
+            self.window_starts.pop();
         }
     }
 
-    pub fn push(&mut self, hash: u64, reset: bool) {
-        if self.count < self.hashes.len() {
-            self.hashes[self.count] = hash;
-            self.start_indices[self.count + 1] = if reset { self.count as u32 } else { self.start_indices[self.count] };
+    /// Whether the position `key`, reached by a move that is `irreversible` or not and not yet
+    /// pushed, should be scored as a draw. That is the case if it repeats a position after the
+    /// root, as the side to move could then repeat it again, or if it occurred twice before.
+    pub fn is_draw(&self, key: u64, irreversible: bool) -> bool {
+        let start = self.window_start(irreversible);
+        let mut count = 0;
+
+        // Only positions with the same side to move can be equal
+        for i in (start..self.keys.len().saturating_sub(1)).rev().step_by(2) {
+            if self.keys[i] == key {
+                if i >= self.root_len {
+                    return true;
+                }
+
+                count += 1;
+                if count >= 2 {
+                    return true;
+                }
+            }
         }
-        self.count += 1;
+
+        false
     }
 
-    pub fn pop(&mut self) {
-        self.count = self.count.max(1) - 1;
+    /// How many times the last pushed position has occurred, including itself.
+    pub fn occurrences(&self) -> usize {
+        let Some(&key) = self.keys.last() else {
+            return 0;
+        };
+
+        let start = self.window_starts[self.keys.len() - 1];
+        (start..self.keys.len()).rev().step_by(2).filter(|&i| self.keys[i] == key).count()
     }
 
-    pub fn contains(&self, hash: u64) -> bool {
-        if self.count >= self.hashes.len() {
-            return false;
+    fn window_start(&self, irreversible: bool) -> usize {
+        if irreversible {
+            self.keys.len()
+        } else {
+            self.window_starts.last().copied().unwrap_or(0)
         }
+    }
+}
 
-        let s = self.start_indices[self.count] as usize;
 
-        for h in self.hashes.iter().skip(s).take(self.count - 1) {
-            if *h == hash {
-                return true;
-            }
+#[cfg(test)]
+mod tests {
+    use crate::{board::{moves::Move, zobrist::Zobrist, Board}, move_gen::magics, precomp};
+    use super::RepetitionTable;
+
+    const KNIGHT_SHUFFLE: [(i8, i8); 4] = [(6, 21), (62, 45), (21, 6), (45, 62)];
+
+    fn board() -> (Board, Zobrist) {
+        precomp::initialize();
+        magics::initialize();
+        let mut zobrist = Zobrist::new();
+        let board = Board::load_position(None, &mut zobrist);
+        (board, zobrist)
+    }
+
+    #[test]
+    fn test_game_history() {
+        let (mut board, zobrist) = board();
+
+        for (start, end) in KNIGHT_SHUFFLE.iter().cycle().take(8) {
+            board.make_move(Move::from_start_end(*start, *end), false, &zobrist);
         }
 
-        false
+        assert_eq!(RepetitionTable::new(&board).occurrences(), 3);
+
+        // A pawn move resets the history
+        board.make_move(Move::from_start_end(12, 20), false, &zobrist);
+        assert_eq!(RepetitionTable::new(&board).occurrences(), 1);
+    }
+
+    #[test]
+    fn test_search_path() {
+        let (board, _) = board();
+        let mut table = RepetitionTable::new(&board);
+        let root = board.current_state.zobrist_key;
+
+        // Repeating the root once isn't a draw, repeating a position after the root is
+        table.push(1, false);
+        table.push(2, false);
+        table.push(3, false);
+        assert!(!table.is_draw(root, false));
+        table.push(root, false);
+        table.push(1, false);
+        table.push(2, false);
+        table.push(3, false);
+        assert!(table.is_draw(2, false));
+        assert!(!table.is_draw(2, true));
+
+        // Positions before an irreversible move can't repeat
+        table.push(4, true);
+        table.push(5, false);
+        assert!(!table.is_draw(3, false));
+        assert!(table.is_draw(4, false));
+
+        for _ in 0..9 {
+            table.pop();
+        }
+        assert_eq!(table.occurrences(), 1);
+        assert!(!table.is_draw(root, false));
+    }
+
+    #[test]
+    fn test_no_length_cap() {
+        let (board, _) = board();
+        let mut table = RepetitionTable::new(&board);
+
+        for key in 0..1000 {
+            table.push(key + 10, false);
+        }
+
+        assert!(table.is_draw(20, false));
+        assert!(!table.is_draw(21, false));
     }
 }
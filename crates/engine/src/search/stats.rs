@@ -0,0 +1,111 @@
+use std::fmt;
+
+/// Counters describing how a search went, for tuning the search and comparing versions.
+///
+/// These are only collected when the engine is built with the `search-stats` feature, in which
+/// case the main thread prints them to stderr after every search. Without the feature, the
+/// [`count_stat`] calls compile to nothing.
+#[derive(Default, Clone)]
+pub struct SearchStats {
+    /// Every node visited, including quiescence nodes
+    pub nodes: u64,
+    pub qnodes: u64,
+    pub tt_probes: u64,
+    /// Probes that found an entry for the position
+    pub tt_hits: u64,
+    /// Probes whose entry was deep enough and had a bound that ended the search of the node
+    pub tt_cutoffs: u64,
+    pub beta_cutoffs: u64,
+    /// Beta cutoffs caused by the first move searched at the node
+    pub first_move_cutoffs: u64,
+    /// Moves searched at a reduced depth by late move reductions
+    pub lmr_searches: u64,
+    /// Reduced searches that beat alpha and had to be searched again at full depth
+    pub lmr_researches: u64,
+    /// Plies of extension applied, for checks, singular moves and the like
+    pub extensions: u64,
+    /// Nodes searched by each completed iteration
+    pub iteration_nodes: Vec<u64>,
+}
+
+impl SearchStats {
+    /// Records the end of an iteration, given the number of nodes searched so far.
+    pub fn end_iteration(&mut self, total_nodes: u64) {
+        let previous: u64 = self.iteration_nodes.iter().sum();
+        self.iteration_nodes.push(total_nodes.saturating_sub(previous));
+    }
+
+    /// How many times more nodes each iteration took than the one before it.
+    pub fn branching_factors(&self) -> Vec<f64> {
+        self.iteration_nodes.windows(2).map(|w| w[1] as f64 / w[0].max(1) as f64).collect()
+    }
+
+    pub fn tt_hit_rate(&self) -> f64 {
+        Self::ratio(self.tt_hits, self.tt_probes)
+    }
+
+    pub fn tt_cutoff_rate(&self) -> f64 {
+        Self::ratio(self.tt_cutoffs, self.tt_probes)
+    }
+
+    /// Share of beta cutoffs that happened on the first move, which measures move ordering.
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        Self::ratio(self.first_move_cutoffs, self.beta_cutoffs)
+    }
+
+    pub fn lmr_research_rate(&self) -> f64 {
+        Self::ratio(self.lmr_researches, self.lmr_searches)
+    }
+
+    fn ratio(n: u64, total: u64) -> f64 {
+        if total == 0 { 0.0 } else { n as f64 / total as f64 }
+    }
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let branching: Vec<String> = self.branching_factors().iter().map(|b| format!("{:.2}", b)).collect();
+
+        writeln!(f, "search statistics")?;
+        writeln!(f, "  nodes              {} ({} quiescence, {:.1}%)", self.nodes, self.qnodes, 100.0 * Self::ratio(self.qnodes, self.nodes))?;
+        writeln!(f, "  tt probes          {}", self.tt_probes)?;
+        writeln!(f, "  tt hits            {} ({:.1}%)", self.tt_hits, 100.0 * self.tt_hit_rate())?;
+        writeln!(f, "  tt cutoffs         {} ({:.1}%)", self.tt_cutoffs, 100.0 * self.tt_cutoff_rate())?;
+        writeln!(f, "  beta cutoffs       {} ({:.1}% on the first move)", self.beta_cutoffs, 100.0 * self.first_move_cutoff_rate())?;
+        writeln!(f, "  lmr searches       {} ({:.1}% searched again)", self.lmr_searches, 100.0 * self.lmr_research_rate())?;
+        writeln!(f, "  extensions         {}", self.extensions)?;
+        write!(f, "  branching factor   {}", branching.join(" "))
+    }
+}
+
+/// Adds `$n` (1 if left out) to a counter in the [`SearchStats`] of `$searcher`. Does nothing,
+/// not even evaluate `$n`, without the `search-stats` feature.
+macro_rules! count_stat {
+    ($searcher:ident . $stat:ident) => {
+        count_stat!($searcher.$stat, 1)
+    };
+    ($searcher:ident . $stat:ident, $n:expr) => {
+        #[cfg(feature = "search-stats")]
+        {
+            $searcher.stats.$stat += $n as u64;
+        }
+    };
+}
+
+pub(crate) use count_stat;
+
+
+#[cfg(test)]
+mod tests {
+    use super::SearchStats;
+
+    #[test]
+    fn test_branching_factors() {
+        let mut stats = SearchStats::default();
+        for total in [10, 50, 250, 1000] {
+            stats.end_iteration(total);
+        }
+        assert_eq!(stats.iteration_nodes, vec![10, 40, 200, 750]);
+        assert_eq!(stats.branching_factors(), vec![4.0, 5.0, 3.75]);
+    }
+}
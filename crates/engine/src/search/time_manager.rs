@@ -0,0 +1,178 @@
+use std::time::{Duration, Instant};
+
+use crate::board::moves::Move;
+
+use super::options::SearchOptions;
+
+/// Decides how long a search may run based on the movetime or the clock state given in the
+/// [`SearchOptions`]. Deterministic searches have no time limits.
+///
+/// The hard deadline aborts the search wherever it is. The soft deadline is only checked between
+/// iterations and is scaled by how stable the best move has been and whether the score is
+/// dropping, so that easy moves are played quickly and troubled positions get more time.
+///
+/// While pondering, neither deadline is ever reached. The clock only starts running at the
+/// ponderhit.
+pub struct TimeManager {
+    start: Instant,
+    pondering: bool,
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+    stable_iterations: u32,
+    prev_best_move: Move,
+    prev_score: Option<i32>,
+    scale: f32,
+}
+
+impl TimeManager {
+    /// Time in milliseconds kept in reserve to account for communication delays.
+    const MOVE_OVERHEAD: u32 = 20;
+    /// Number of moves the remaining time is split over when the GUI doesn't give `movestogo`.
+    const DEFAULT_MOVES_TO_GO: u32 = 30;
+    /// The hard limit may exceed the planned time per move by at most this factor.
+    const MAX_OVERSHOOT: u32 = 4;
+    /// Score drop (in evaluation units) needed before the search is given extra time.
+    const SCORE_DROP_MARGIN: i32 = 30;
+
+    pub fn new(opts: &SearchOptions, white_to_move: bool) -> Self {
+        let (time, inc) = if white_to_move { (opts.wtime, opts.winc) } else { (opts.btime, opts.binc) };
+
+        let (soft_limit, hard_limit) = if opts.deterministic {
+            (None, None)
+        } else if let Some(movetime) = opts.movetime {
+            (None, Some(movetime))
+        } else if let Some(time) = time {
+            let inc = inc.unwrap_or(0);
+            let moves_to_go = opts.movestogo.unwrap_or(Self::DEFAULT_MOVES_TO_GO).max(1);
+            let available = time.saturating_sub(Self::MOVE_OVERHEAD).max(1);
+
+            let max_time = if moves_to_go == 1 { available } else { available * 3 / 4 };
+            let hard = (available / moves_to_go + inc * 3 / 4).saturating_mul(Self::MAX_OVERSHOOT).min(max_time);
+            let soft = (available / moves_to_go + inc * 3 / 4).min(hard);
+
+            (Some(soft), Some(hard))
+        } else {
+            (None, None)
+        };
+
+        Self {
+            start: Instant::now(),
+            pondering: opts.ponder,
+            soft_limit: soft_limit.map(|t| Duration::from_millis(t as u64)),
+            hard_limit: hard_limit.map(|t| Duration::from_millis(t as u64)),
+            stable_iterations: 0,
+            prev_best_move: Move::NULL,
+            prev_score: None,
+            scale: 1.0,
+        }
+    }
+
+    /// Restarts the clock, keeping the deadlines.
+    pub fn restart(&mut self) {
+        self.start = Instant::now();
+    }
+
+    /// Whether the search is still running on the opponent's time.
+    pub fn pondering(&self) -> bool {
+        self.pondering
+    }
+
+    /// The opponent played the expected move, so the search now runs on our own time.
+    pub fn ponderhit(&mut self) {
+        self.pondering = false;
+        self.restart();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Whether the search must stop immediately.
+    pub fn hard_limit_reached(&self) -> bool {
+        !self.pondering && self.hard_limit.is_some_and(|limit| self.elapsed() >= limit)
+    }
+
+    /// Whether another iteration should not be started.
+    pub fn soft_limit_reached(&self) -> bool {
+        !self.pondering && self.soft_limit.is_some_and(|limit| self.elapsed() >= limit.mul_f32(self.scale))
+    }
+
+    /// Updates the soft deadline with the result of a completed iteration.
+    pub fn update(&mut self, best_move: Move, score: i32) {
+        if best_move == self.prev_best_move {
+            self.stable_iterations += 1;
+        } else {
+            self.stable_iterations = 0;
+        }
+
+        // A best move that has survived many iterations is unlikely to change, so spend less
+        // time on it. A freshly changed best move gets a little extra.
+        let stability = 1.3 - 0.1 * self.stable_iterations.min(7) as f32;
+
+        // When the score drops, the position is likely harder than it seemed, so extend.
+        let drop = self.prev_score.map_or(0, |prev| prev - score);
+        let instability = if drop > Self::SCORE_DROP_MARGIN {
+            1.0 + (drop as f32 / 300.0).min(1.0)
+        } else {
+            1.0
+        };
+
+        self.scale = stability * instability;
+        self.prev_best_move = best_move;
+        self.prev_score = Some(score);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{board::moves::Move, search::options::SearchOptions};
+    use super::TimeManager;
+
+    #[test]
+    fn test_movetime() {
+        let opts = SearchOptions { movetime: Some(500), ..Default::default() };
+        let tm = TimeManager::new(&opts, true);
+        assert_eq!(tm.soft_limit, None);
+        assert_eq!(tm.hard_limit, Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_clock() {
+        let opts = SearchOptions { movetime: None, wtime: Some(60000), btime: Some(1000), winc: Some(1000), ..Default::default() };
+        let white = TimeManager::new(&opts, true);
+        let black = TimeManager::new(&opts, false);
+        assert!(white.soft_limit.unwrap() < white.hard_limit.unwrap());
+        assert!(white.hard_limit.unwrap() < Duration::from_millis(60000));
+        assert!(black.hard_limit.unwrap() < Duration::from_millis(1000));
+        assert!(black.soft_limit.unwrap() < white.soft_limit.unwrap());
+    }
+
+    #[test]
+    fn test_ponder() {
+        let opts = SearchOptions { movetime: Some(0), ponder: true, ..Default::default() };
+        let mut tm = TimeManager::new(&opts, true);
+        assert!(!tm.hard_limit_reached());
+        assert!(!tm.soft_limit_reached());
+
+        tm.ponderhit();
+        assert!(!tm.pondering());
+        assert!(tm.hard_limit_reached());
+    }
+
+    #[test]
+    fn test_stability() {
+        let opts = SearchOptions { movetime: None, wtime: Some(60000), ..Default::default() };
+        let mut tm = TimeManager::new(&opts, true);
+        let m = Move::from_start_end(12, 28);
+        for _ in 0..8 {
+            tm.update(m, 50);
+        }
+        let stable = tm.scale;
+        tm.update(m, -200);
+        assert!(stable < 1.0);
+        assert!(tm.scale > stable);
+    }
+}
@@ -0,0 +1,346 @@
+use std::{fs, io::{self, BufWriter, Write}, path::{Path, PathBuf}};
+
+use crate::board::moves::Move;
+
+use super::transpositions::TranspositionNodeType;
+
+/// Where and how much of the search tree to write out. See [`Tracer`].
+#[derive(Clone)]
+pub struct TraceOptions {
+    pub path: PathBuf,
+    pub format: TraceFormat,
+    /// Nodes further than this many plies from the root are left out
+    pub max_ply: u8,
+    /// Stop recording after this many nodes
+    pub max_nodes: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TraceFormat {
+    /// One node per line, which can be read back with [`read_json`]
+    Json,
+    /// A graph for Graphviz, for paths ending in `.dot` or `.gv`
+    Dot,
+}
+
+impl TraceFormat {
+    /// DOT for paths ending in `.dot` or `.gv`, JSON otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("dot" | "gv") => Self::Dot,
+            _ => Self::Json,
+        }
+    }
+}
+
+/// A node of the search tree as it was visited.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TraceNode {
+    pub id: usize,
+    pub parent: Option<usize>,
+    pub ply: u8,
+    /// The move that led to this node, `root` for the root of an iteration and `null` for a null
+    /// move
+    pub m: String,
+    /// Remaining depth
+    pub depth: u8,
+    pub alpha: i32,
+    pub beta: i32,
+    /// `None` if the node was never searched, or the search was stopped before it finished
+    pub score: Option<i32>,
+    /// What the score says about the node relative to the window it was searched with
+    pub bound: Option<TranspositionNodeType>,
+    /// Whether the transposition table had an entry for the position
+    pub tt_hit: bool,
+    /// Why the search of the node ended early, or why it was skipped
+    pub pruning: Option<String>,
+}
+
+impl TraceNode {
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"id\":{},\"parent\":{},\"ply\":{},\"move\":\"{}\",\"depth\":{},\"alpha\":{},\"beta\":{},\"score\":{},\"bound\":{},\"tt_hit\":{},\"pruning\":{}}}",
+            self.id,
+            self.parent.map_or(String::from("null"), |p| p.to_string()),
+            self.ply,
+            self.m,
+            self.depth,
+            self.alpha,
+            self.beta,
+            self.score.map_or(String::from("null"), |s| s.to_string()),
+            self.bound.map_or(String::from("null"), |b| format!("\"{}\"", bound_name(b))),
+            self.tt_hit,
+            self.pruning.as_ref().map_or(String::from("null"), |p| format!("\"{}\"", p)),
+        )
+    }
+
+    /// Parses a node written by [`TraceNode::to_json`]. This is not a general JSON parser: it
+    /// only reads flat objects whose strings contain no commas or escapes.
+    pub fn from_json(line: &str) -> Option<Self> {
+        let body = line.trim().trim_end_matches(',').strip_prefix('{')?.strip_suffix('}')?;
+
+        let mut node = Self {
+            id: 0,
+            parent: None,
+            ply: 0,
+            m: String::new(),
+            depth: 0,
+            alpha: 0,
+            beta: 0,
+            score: None,
+            bound: None,
+            tt_hit: false,
+            pruning: None,
+        };
+
+        for field in body.split(',') {
+            let (key, value) = field.split_once(':')?;
+            let string = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'));
+
+            match key.trim_matches('"') {
+                "id" => node.id = value.parse().ok()?,
+                "parent" => node.parent = if value == "null" { None } else { Some(value.parse().ok()?) },
+                "ply" => node.ply = value.parse().ok()?,
+                "move" => node.m = string?.to_string(),
+                "depth" => node.depth = value.parse().ok()?,
+                "alpha" => node.alpha = value.parse().ok()?,
+                "beta" => node.beta = value.parse().ok()?,
+                "score" => node.score = if value == "null" { None } else { Some(value.parse().ok()?) },
+                "bound" => node.bound = string.and_then(parse_bound),
+                "tt_hit" => node.tt_hit = value.parse().ok()?,
+                "pruning" => node.pruning = string.map(String::from),
+                _ => (),
+            }
+        }
+
+        Some(node)
+    }
+}
+
+fn bound_name(bound: TranspositionNodeType) -> &'static str {
+    match bound {
+        TranspositionNodeType::Exact => "exact",
+        TranspositionNodeType::LowerBound => "lower",
+        TranspositionNodeType::UpperBound => "upper",
+    }
+}
+
+fn parse_bound(name: &str) -> Option<TranspositionNodeType> {
+    match name {
+        "exact" => Some(TranspositionNodeType::Exact),
+        "lower" => Some(TranspositionNodeType::LowerBound),
+        "upper" => Some(TranspositionNodeType::UpperBound),
+        _ => None,
+    }
+}
+
+/// Reads the nodes of a tree written in the JSON format.
+pub fn read_json(path: &Path) -> io::Result<Vec<TraceNode>> {
+    let contents = fs::read_to_string(path)?;
+    contents.lines()
+        .filter(|line| line.trim_start().starts_with('{'))
+        .map(|line| TraceNode::from_json(line).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("malformed node: {}", line))))
+        .collect()
+}
+
+
+/// Records the tree explored by a search, so that it can be inspected after the fact.
+///
+/// Every search of a node is recorded as it happens, so a node searched again with a different
+/// window or depth shows up once per search, and each iteration of iterative deepening adds a new
+/// root. Nodes beyond `max_ply` are left out, as are all nodes after the first `max_nodes`.
+pub struct Tracer {
+    opts: TraceOptions,
+    nodes: Vec<TraceNode>,
+    /// The nodes currently being searched, `None` for those that aren't recorded
+    stack: Vec<Option<usize>>,
+}
+
+impl Tracer {
+    pub fn new(opts: TraceOptions) -> Self {
+        Self {
+            opts,
+            nodes: Vec::new(),
+            stack: Vec::new(),
+        }
+    }
+
+    pub fn nodes(&self) -> &[TraceNode] {
+        &self.nodes
+    }
+
+    /// Starts searching a node reached by `m`. Must be matched by a call to [`Tracer::exit`].
+    pub fn enter(&mut self, ply: u8, m: Move, depth: u8, alpha: i32, beta: i32) {
+        let parent = self.stack.last().copied();
+        let id = self.push(parent, ply, m, depth, alpha, beta);
+        self.stack.push(id);
+    }
+
+    /// Finishes the node being searched with its score, or `None` if the search was stopped.
+    pub fn exit(&mut self, score: Option<i32>) {
+        let Some(Some(id)) = self.stack.pop() else {
+            return;
+        };
+
+        let node = &mut self.nodes[id];
+        node.score = score;
+        node.bound = score.map(|s| if s <= node.alpha {
+            TranspositionNodeType::UpperBound
+        } else if s >= node.beta {
+            TranspositionNodeType::LowerBound
+        } else {
+            TranspositionNodeType::Exact
+        });
+    }
+
+    pub fn note_tt_hit(&mut self) {
+        if let Some(Some(id)) = self.stack.last() {
+            self.nodes[*id].tt_hit = true;
+        }
+    }
+
+    /// Records why the search of the current node is ending early.
+    pub fn note_pruning(&mut self, reason: &str) {
+        if let Some(Some(id)) = self.stack.last() {
+            self.nodes[*id].pruning = Some(reason.to_string());
+        }
+    }
+
+    /// Records a move of the current node that is skipped without being searched.
+    pub fn pruned_move(&mut self, ply: u8, m: Move, depth: u8, reason: &str) {
+        if let Some(&parent) = self.stack.last() {
+            if let Some(id) = self.push(Some(parent), ply, m, depth, 0, 0) {
+                self.nodes[id].pruning = Some(reason.to_string());
+            }
+        }
+    }
+
+    /// Adds a node if it is within the budget and its parent was recorded.
+    fn push(&mut self, parent: Option<Option<usize>>, ply: u8, m: Move, depth: u8, alpha: i32, beta: i32) -> Option<usize> {
+        let parent = match parent {
+            Some(None) => return None,
+            Some(Some(id)) => Some(id),
+            None => None,
+        };
+        if ply > self.opts.max_ply || self.nodes.len() >= self.opts.max_nodes {
+            return None;
+        }
+
+        let id = self.nodes.len();
+        self.nodes.push(TraceNode {
+            id,
+            parent,
+            ply,
+            m: if parent.is_none() { String::from("root") } else if m == Move::NULL { String::from("null") } else { m.name() },
+            depth,
+            alpha,
+            beta,
+            score: None,
+            bound: None,
+            tt_hit: false,
+            pruning: None,
+        });
+
+        Some(id)
+    }
+
+    /// Writes the recorded tree to the file given in the options.
+    pub fn write(&self) -> io::Result<()> {
+        let mut file = BufWriter::new(fs::File::create(&self.opts.path)?);
+
+        match self.opts.format {
+            TraceFormat::Json => {
+                writeln!(file, "[")?;
+                for (i, node) in self.nodes.iter().enumerate() {
+                    let separator = if i + 1 < self.nodes.len() { "," } else { "" };
+                    writeln!(file, "{}{}", node.to_json(), separator)?;
+                }
+                writeln!(file, "]")?;
+            },
+            TraceFormat::Dot => {
+                writeln!(file, "digraph search {{")?;
+                writeln!(file, "  node [shape=box, fontname=monospace];")?;
+                for node in self.nodes.iter() {
+                    let score = node.score.map_or(String::from("-"), |s| s.to_string());
+                    let bound = node.bound.map_or("", bound_name);
+                    let mut label = format!("{} d{}\\n[{}, {}] {} {}", node.m, node.depth, node.alpha, node.beta, score, bound);
+                    if let Some(reason) = &node.pruning {
+                        label.push_str(&format!("\\n{}", reason));
+                    }
+
+                    let style = if node.pruning.is_some() { ", style=dashed" } else if node.tt_hit { ", color=blue" } else { "" };
+                    writeln!(file, "  n{} [label=\"{}\"{}];", node.id, label, style)?;
+                    if let Some(parent) = node.parent {
+                        writeln!(file, "  n{} -> n{};", parent, node.id)?;
+                    }
+                }
+                writeln!(file, "}}")?;
+            },
+        }
+
+        file.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::{board::moves::Move, search::transpositions::TranspositionNodeType};
+    use super::{TraceFormat, TraceNode, TraceOptions, Tracer};
+
+    fn tracer(max_ply: u8, max_nodes: usize) -> Tracer {
+        Tracer::new(TraceOptions { path: PathBuf::new(), format: TraceFormat::Json, max_ply, max_nodes })
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut tracer = tracer(8, 100);
+        tracer.enter(0, Move::NULL, 3, -40, 40);
+        tracer.enter(1, Move::from_start_end(12, 28), 2, -40, 40);
+        tracer.note_tt_hit();
+        tracer.note_pruning("null move");
+        tracer.exit(Some(-50));
+        tracer.pruned_move(1, Move::from_start_end(6, 21), 2, "futility");
+        tracer.exit(Some(50));
+
+        let nodes = tracer.nodes();
+        assert_eq!(nodes[0].bound, Some(TranspositionNodeType::LowerBound));
+        assert_eq!(nodes[1].bound, Some(TranspositionNodeType::UpperBound));
+        assert_eq!(nodes[2].parent, Some(0));
+        assert_eq!(nodes[2].score, None);
+        for node in nodes {
+            assert_eq!(TraceNode::from_json(&format!("{},", node.to_json())).as_ref(), Some(node));
+        }
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(TraceFormat::from_path(Path::new("tree.dot")), TraceFormat::Dot);
+        assert_eq!(TraceFormat::from_path(Path::new("tree.gv")), TraceFormat::Dot);
+        assert_eq!(TraceFormat::from_path(Path::new("tree.json")), TraceFormat::Json);
+        assert_eq!(TraceFormat::from_path(Path::new("tree")), TraceFormat::Json);
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut tracer = tracer(1, 3);
+        tracer.enter(0, Move::NULL, 3, -40, 40);
+        tracer.enter(1, Move::from_start_end(12, 28), 2, -40, 40);
+        // Too deep, and so are the moves it skips
+        tracer.enter(2, Move::from_start_end(52, 36), 1, -40, 40);
+        tracer.pruned_move(3, Move::from_start_end(6, 21), 0, "futility");
+        tracer.exit(Some(0));
+        tracer.exit(Some(0));
+        tracer.enter(1, Move::from_start_end(11, 27), 2, -40, 40);
+        tracer.exit(Some(0));
+        // Over the node budget
+        tracer.enter(1, Move::from_start_end(10, 26), 2, -40, 40);
+        tracer.exit(Some(0));
+        tracer.exit(Some(0));
+
+        assert_eq!(tracer.nodes().len(), 3);
+        assert!(tracer.nodes().iter().all(|n| n.ply <= 1));
+    }
+}
@@ -1,10 +1,10 @@
-use std::ops::Index;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
 
 use crate::board::moves::Move;
 
 use super::Searcher;
 
-#[derive(Default, Clone, Copy, PartialEq, Eq)]
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum TranspositionNodeType {
     #[default]
     Exact,
@@ -12,55 +12,193 @@ pub enum TranspositionNodeType {
     UpperBound,
 }
 
-#[derive(Default, Clone, Copy)]
+impl TranspositionNodeType {
+    fn from_bits(bits: u8) -> Self {
+        match bits {
+            2 => Self::LowerBound,
+            3 => Self::UpperBound,
+            _ => Self::Exact,
+        }
+    }
+
+    /// Zero is reserved for empty entries.
+    fn to_bits(self) -> u8 {
+        match self {
+            Self::Exact => 1,
+            Self::LowerBound => 2,
+            Self::UpperBound => 3,
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug)]
 pub struct TranspositionEntry {
-    pub key: u64,
     pub eval: i32,
     pub m: Move,
     pub depth: u8,
     pub node_type: TranspositionNodeType,
+    pub generation: u8,
 }
 
 impl TranspositionEntry {
-    pub fn new(key: u64, eval: i32, m: Move, depth: u8, node_type: TranspositionNodeType) -> Self {
+    // Layout of a packed entry, from the least significant bit:
+    // 16 bits key | 16 bits move | 16 bits eval | 8 bits depth | 6 bits generation | 2 bits bound
+    const MOVE_SHIFT: u64 = 16;
+    const EVAL_SHIFT: u64 = 32;
+    const DEPTH_SHIFT: u64 = 48;
+    const GENERATION_SHIFT: u64 = 56;
+    const BOUND_SHIFT: u64 = 62;
+
+    const KEY_SHIFT: u64 = 48;
+    const GENERATION_MASK: u8 = 0b111111;
+
+    const MAX_STORED_EVAL: i32 = 30000;
+    const STORED_MATE_SCORE: i32 = 32000;
+
+    pub fn new(eval: i32, m: Move, depth: u8, node_type: TranspositionNodeType, generation: u8) -> Self {
         Self {
-            key,
             eval,
             m,
             depth,
             node_type,
+            generation,
         }
     }
 
     pub const fn size() -> usize {
-        std::mem::size_of::<Self>()
+        std::mem::size_of::<AtomicU64>()
+    }
+
+    /// The bits of the zobrist key that are stored in the entry to tell positions apart. The
+    /// remaining bits are implied by the bucket the entry is stored in.
+    fn key_check(key: u64) -> u64 {
+        key >> Self::KEY_SHIFT
+    }
+
+    fn pack(&self, key: u64) -> u64 {
+        Self::key_check(key)
+            | (self.m.value() as u64) << Self::MOVE_SHIFT
+            | (Self::compress_eval(self.eval) as u16 as u64) << Self::EVAL_SHIFT
+            | (self.depth as u64) << Self::DEPTH_SHIFT
+            | ((self.generation & Self::GENERATION_MASK) as u64) << Self::GENERATION_SHIFT
+            | (self.node_type.to_bits() as u64) << Self::BOUND_SHIFT
+    }
+
+    fn unpack(data: u64) -> Self {
+        Self {
+            m: Move::from_value((data >> Self::MOVE_SHIFT) as u16),
+            eval: Self::decompress_eval((data >> Self::EVAL_SHIFT) as u16 as i16),
+            depth: (data >> Self::DEPTH_SHIFT) as u8,
+            generation: (data >> Self::GENERATION_SHIFT) as u8 & Self::GENERATION_MASK,
+            node_type: TranspositionNodeType::from_bits((data >> Self::BOUND_SHIFT) as u8),
+        }
+    }
+
+    fn matches(data: u64, key: u64) -> bool {
+        data != 0 && data & 0xFFFF == Self::key_check(key)
+    }
+
+    /// Fits an evaluation into 16 bits. Mate scores keep their exact distance to mate while other
+    /// scores are clamped, which only affects positions that are won by a huge margin anyway.
+    fn compress_eval(eval: i32) -> i16 {
+        if eval.abs() > Searcher::IMMEDIATE_MATE_SCORE - 1000 {
+            (eval.signum() * (Self::STORED_MATE_SCORE - (Searcher::IMMEDIATE_MATE_SCORE - eval.abs()))) as i16
+        } else {
+            eval.clamp(-Self::MAX_STORED_EVAL, Self::MAX_STORED_EVAL) as i16
+        }
+    }
+
+    fn decompress_eval(eval: i16) -> i32 {
+        let eval = eval as i32;
+        if eval.abs() > Self::MAX_STORED_EVAL {
+            eval.signum() * (Searcher::IMMEDIATE_MATE_SCORE - (Self::STORED_MATE_SCORE - eval.abs()))
+        } else {
+            eval
+        }
     }
 }
 
 
-pub struct TranspositionTable<const N: usize> {
-    entries: Vec<TranspositionEntry>,
+/// A group of entries that share an index, aligned so that two buckets fill a cache line.
+///
+/// Every entry is a single atomic word, so search threads can read and write the table at the
+/// same time without locking and without ever seeing a torn entry.
+#[derive(Default)]
+#[repr(align(32))]
+struct Bucket {
+    entries: [AtomicU64; Bucket::SIZE],
+}
+
+impl Bucket {
+    const SIZE: usize = 4;
+}
+
+
+/// A transposition table that can be shared between search threads.
+///
+/// Positions hash to a bucket of several entries. When storing into a full bucket, the entry
+/// that is cheapest to lose is replaced: shallow entries and entries left over from earlier
+/// searches go first. The generation is bumped once per search to tell the two apart.
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    generation: AtomicU8,
     enabled: bool,
 }
 
-impl<const N: usize> TranspositionTable<N> {
-    pub fn new() -> Self {
+impl TranspositionTable {
+    pub const DEFAULT_SIZE_MB: usize = 64;
+    pub const MAX_SIZE_MB: usize = 65536;
+
+    /// Creates a table that takes up `size_mb` megabytes.
+    pub fn new(size_mb: usize) -> Self {
+        let n_buckets = (size_mb.clamp(1, Self::MAX_SIZE_MB) * 1024 * 1024 / std::mem::size_of::<Bucket>()).max(1);
+
         Self {
-            entries: vec![TranspositionEntry::default(); N],
+            buckets: (0..n_buckets).map(|_| Bucket::default()).collect(),
+            generation: AtomicU8::new(0),
             enabled: true,
         }
     }
 
-    pub fn clear(&mut self) {
-        self.entries.iter_mut().for_each(|e| *e = TranspositionEntry::default());
+    /// The size of the table in megabytes.
+    pub fn size_mb(&self) -> usize {
+        self.buckets.len() * std::mem::size_of::<Bucket>() / (1024 * 1024)
+    }
+
+    pub fn clear(&self) {
+        self.buckets.iter().flat_map(|b| b.entries.iter()).for_each(|e| e.store(0, Ordering::Relaxed));
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// Marks the start of a new search so that entries from previous searches age out.
+    pub fn new_search(&self) {
+        let generation = (self.generation.load(Ordering::Relaxed) + 1) & TranspositionEntry::GENERATION_MASK;
+        self.generation.store(generation, Ordering::Relaxed);
+    }
+
+    pub fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed)
     }
 
     pub fn index(&self, key: u64) -> u64 {
-        key % N as u64
+        key % self.buckets.len() as u64
+    }
+
+    /// How full the table is in permille, estimated from the first thousand entries. Only entries
+    /// written during the current search are counted.
+    pub fn hashfull(&self) -> u32 {
+        let generation = self.generation();
+        let sample = (1000 / Bucket::SIZE).min(self.buckets.len());
+        let used = self.buckets.iter().take(sample).flat_map(|b| b.entries.iter()).filter(|e| {
+            let data = e.load(Ordering::Relaxed);
+            data != 0 && TranspositionEntry::unpack(data).generation == generation
+        }).count();
+
+        (used * 1000 / (sample * Bucket::SIZE)) as u32
     }
 
     pub fn get_stored_move(&self, key: u64) -> Move {
-        self.entries[self.index(key) as usize].m
+        self.get(key).map_or(Move::NULL, |entry| entry.m)
     }
 
     pub fn lookup(&self, key: u64, depth: u8, dst_from_root: u8, alpha: i32, beta: i32) -> Option<i32> {
@@ -68,9 +206,9 @@ impl<const N: usize> TranspositionTable<N> {
             return None;
         }
 
-        let entry = self.entries[self.index(key) as usize];
+        let entry = self.get(key)?;
 
-        if entry.key == key && entry.depth >= depth {
+        if entry.depth >= depth {
             let score = Self::correct_retrieved_mate_score(entry.eval, dst_from_root);
 
             if entry.node_type == TranspositionNodeType::Exact {
@@ -80,7 +218,7 @@ impl<const N: usize> TranspositionTable<N> {
             if entry.node_type == TranspositionNodeType::UpperBound && score <= alpha {
                 return Some(score);
             }
-            
+
             if entry.node_type == TranspositionNodeType::LowerBound && score >= beta {
                 return Some(score);
             }
@@ -89,14 +227,51 @@ impl<const N: usize> TranspositionTable<N> {
         None
     }
 
-    pub fn store(&mut self, key: u64, depth: u8, dst_from_root: u8, eval: i32, eval_type: TranspositionNodeType, m: Move) {
+    pub fn store(&self, key: u64, depth: u8, dst_from_root: u8, eval: i32, eval_type: TranspositionNodeType, mut m: Move) {
         if !self.enabled {
             return;
         }
 
-        let index = self.index(key) as usize;
-        let entry = TranspositionEntry::new(key, Self::correct_store_mate_score(eval, dst_from_root), m, depth, eval_type);
-        self.entries[index] = entry;
+        let generation = self.generation();
+        let bucket = &self.buckets[self.index(key) as usize];
+        let mut replace = &bucket.entries[0];
+        let mut replace_worth = i32::MAX;
+
+        for slot in bucket.entries.iter() {
+            let data = slot.load(Ordering::Relaxed);
+
+            // Overwrite the entry for this position, unless it was searched much deeper during
+            // this search and we don't have an exact score to replace it with.
+            if TranspositionEntry::matches(data, key) {
+                let old = TranspositionEntry::unpack(data);
+                if eval_type != TranspositionNodeType::Exact && old.generation == generation && old.depth > depth.saturating_add(2) {
+                    return;
+                }
+
+                if m.is_invalid() {
+                    m = old.m;
+                }
+
+                replace = slot;
+                break;
+            }
+
+            // Otherwise prefer replacing shallow entries and entries from earlier searches
+            let worth = if data == 0 { i32::MIN } else { self.worth(TranspositionEntry::unpack(data), generation) };
+            if worth < replace_worth {
+                replace = slot;
+                replace_worth = worth;
+            }
+        }
+
+        let entry = TranspositionEntry::new(Self::correct_store_mate_score(eval, dst_from_root), m, depth, eval_type, generation);
+        replace.store(entry.pack(key), Ordering::Relaxed);
+    }
+
+    /// How much we'd lose by replacing this entry.
+    fn worth(&self, entry: TranspositionEntry, generation: u8) -> i32 {
+        let age = generation.wrapping_sub(entry.generation) & TranspositionEntry::GENERATION_MASK;
+        entry.depth as i32 - 8 * age as i32
     }
 
     pub fn correct_store_mate_score(score: i32, num_positions_searched: u8) -> i32 {
@@ -117,13 +292,93 @@ impl<const N: usize> TranspositionTable<N> {
         }
     }
 
-    pub fn get(&self, key: u64) -> TranspositionEntry {
-        self.entries[(key % N as u64) as usize]
+    /// Returns the entry stored for `key`, if there is one.
+    pub fn get(&self, key: u64) -> Option<TranspositionEntry> {
+        self.buckets[self.index(key) as usize].entries.iter()
+            .map(|e| e.load(Ordering::Relaxed))
+            .find(|data| TranspositionEntry::matches(*data, key))
+            .map(TranspositionEntry::unpack)
     }
 }
 
-impl<const N: usize> Default for TranspositionTable<N> {
+impl Default for TranspositionTable {
     fn default() -> Self {
-        Self::new()
+        Self::new(Self::DEFAULT_SIZE_MB)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::moves::Move, search::Searcher};
+    use super::{Bucket, TranspositionEntry, TranspositionNodeType, TranspositionTable};
+
+    #[test]
+    fn test_size() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(TranspositionEntry::size(), 8);
+        assert_eq!(std::mem::size_of::<Bucket>(), 32);
+        assert_eq!(tt.buckets.len() * Bucket::SIZE, 1024 * 1024 / TranspositionEntry::size());
+    }
+
+    #[test]
+    fn test_store_lookup() {
+        let tt = TranspositionTable::new(1);
+        let m = Move::from_start_end(12, 28);
+        tt.store(0x1234_5678_9ABC_DEF0, 4, 0, 150, TranspositionNodeType::Exact, m);
+        assert_eq!(tt.lookup(0x1234_5678_9ABC_DEF0, 4, 0, -1000, 1000), Some(150));
+        assert_eq!(tt.lookup(0x1234_5678_9ABC_DEF0, 5, 0, -1000, 1000), None);
+        assert_eq!(tt.get_stored_move(0x1234_5678_9ABC_DEF0), m);
+        assert!(tt.get(0x0FED_CBA9_8765_4321).is_none());
+    }
+
+    #[test]
+    fn test_eval_compression() {
+        for eval in [0, 1, -1, 12345, -29999, Searcher::IMMEDIATE_MATE_SCORE - 3, -Searcher::IMMEDIATE_MATE_SCORE + 12] {
+            assert_eq!(TranspositionEntry::decompress_eval(TranspositionEntry::compress_eval(eval)), eval);
+        }
+        assert_eq!(TranspositionEntry::decompress_eval(TranspositionEntry::compress_eval(100000)), 30000);
+    }
+
+    #[test]
+    fn test_replacement() {
+        let tt = TranspositionTable::new(1);
+        let n = tt.buckets.len() as u64;
+        let m = Move::from_start_end(12, 28);
+        // Keys that all hash to the first bucket but have different key checks
+        let key = |i: u64| i * n + (i << 48);
+
+        // Shallow entries replace each other before replacing deep ones
+        for i in 0..4 {
+            tt.store(key(i), 10 + i as u8, 0, 0, TranspositionNodeType::Exact, m);
+        }
+        tt.store(key(5), 1, 0, 0, TranspositionNodeType::Exact, m);
+        tt.store(key(6), 1, 0, 0, TranspositionNodeType::Exact, m);
+        assert!(tt.get(key(0)).is_none());
+        assert!(tt.get(key(5)).is_none());
+        assert!((1..4).all(|i| tt.get(key(i)).is_some()));
+        assert!(tt.get(key(6)).is_some());
+
+        // Entries from old searches are replaced even if they are deep
+        tt.new_search();
+        tt.new_search();
+        for i in 20..24 {
+            tt.store(key(i), 1, 0, 0, TranspositionNodeType::Exact, m);
+        }
+        assert!((20..24).all(|i| tt.get(key(i)).is_some()));
+    }
+
+    #[test]
+    fn test_hashfull() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+        for key in 0..250 {
+            tt.store(key, 1, 0, 0, TranspositionNodeType::Exact, Move::from_start_end(12, 28));
+        }
+        assert_eq!(tt.hashfull(), 250);
+        tt.new_search();
+        assert_eq!(tt.hashfull(), 0);
+        tt.clear();
+        assert_eq!(tt.hashfull(), 0);
     }
 }
@@ -1,4 +1,6 @@
-use engine::{board::{coord::Coord, Board}, game::{Game, PlayerType}, search::options::SearchOptions, utils::fen};
+use std::{str::FromStr, time::Duration};
+
+use engine::{board::{coord::Coord, moves::Move, Board}, game::{Game, PlayerType}, search::{diagnostics::SearchInfo, options::SearchOptions, transpositions::TranspositionTable}, utils::fen};
 use ucimove::move_from_name;
 
 use crate::ucimove::name_from_move;
@@ -6,23 +8,39 @@ use crate::ucimove::name_from_move;
 mod ucimove;
 mod channel;
 
+const MAX_THREADS: usize = 256;
+const MAX_MULTIPV: usize = 256;
+const MAX_CONTEMPT: i32 = 1000;
+
 pub fn start() {
     let stdin = channel::spawn_stdin();
     let mut game = Game::new(None, SearchOptions::default(), PlayerType::Human, PlayerType::Human);
     let mut finished = true;
 
     'main: loop {
-        if let Ok(line) = stdin.try_recv() {
+        if let Ok(line) = stdin.recv_timeout(Duration::from_millis(1)) {
             match line.split_whitespace().next() {
                 None | Some("") => (),
                 Some("uci") => {
+                    println!("id name Chess-a-tron");
+                    println!("id author Killian Hourihan");
+                    println!("option name Threads type spin default 1 min 1 max {}", MAX_THREADS);
+                    println!("option name Hash type spin default {} min 1 max {}", TranspositionTable::DEFAULT_SIZE_MB, TranspositionTable::MAX_SIZE_MB);
+                    println!("option name Clear Hash type button");
+                    println!("option name MultiPV type spin default 1 min 1 max {}", MAX_MULTIPV);
+                    println!("option name Ponder type check default false");
+                    println!("option name Contempt type spin default 0 min {} max {}", -MAX_CONTEMPT, MAX_CONTEMPT);
+                    println!("option name Mate Checks Only type check default false");
                     println!("uciok")
                 },
+                Some("setoption") => {
+                    setoption(&mut game, &line);
+                },
                 Some("isready") => {
                     println!("readyok")
                 },
                 Some("ucinewgame") => {
-                    game.searcher.abort();
+                    game.searcher.new_game();
                     game.board = Board::load_position(None, &mut game.zobrist);
                     finished = true;
                 },
@@ -37,11 +55,14 @@ pub fn start() {
                 Some("d") => {
                     display(&game);
                 },
+                Some("ponderhit") => {
+                    game.searcher.ponderhit();
+                },
                 Some("stop") => {
                     game.searcher.abort();
                 },
                 Some("quit") => {
-                    game.searcher.abort();
+                    game.searcher.force_best_move();
                     break 'main;
                 },
                 Some(cmd) => {
@@ -51,9 +72,15 @@ pub fn start() {
         }
 
         if !finished {
+            game.searcher.poll_info().into_iter().for_each(print_info);
+
             if let Some(bestmove) = game.searcher.best_move() {
-                println!("info depth {}", game.searcher.diagnostics.depth_searched);
-                println!("bestmove {}", name_from_move(bestmove).unwrap());
+                // The search may have reported more progress before finishing
+                game.searcher.poll_info().into_iter().for_each(print_info);
+                match game.searcher.ponder_move().and_then(name_from_move) {
+                    Some(ponder) => println!("bestmove {} ponder {}", name_from_move(bestmove).unwrap(), ponder),
+                    None => println!("bestmove {}", name_from_move(bestmove).unwrap()),
+                }
                 finished = true;
             }
         }
@@ -88,25 +115,124 @@ pub fn position(game: &mut Game, cmd: &str) -> Option<()> {
 }
 
 pub fn go(game: &mut Game, cmd: &str) -> Option<()> {
-    let args = cmd.split_whitespace();
-
-    let mut opts = SearchOptions {
-        movetime: None,
-        depth: None,
+    let opts = SearchOptions {
+        movetime: parse_arg(cmd, "movetime")?,
+        depth: parse_arg(cmd, "depth")?,
+        nodes: parse_arg(cmd, "nodes")?,
+        mate: parse_arg(cmd, "mate")?,
+        wtime: parse_arg(cmd, "wtime")?,
+        btime: parse_arg(cmd, "btime")?,
+        winc: parse_arg(cmd, "winc")?,
+        binc: parse_arg(cmd, "binc")?,
+        movestogo: parse_arg(cmd, "movestogo")?,
+        ponder: cmd.split_whitespace().any(|s| s == "ponder"),
+        search_moves: parse_searchmoves(&game.board, cmd),
+        ..game.search_opts.clone()
     };
 
-    if let Some(i) = args.clone().position(|s| s == "movetime") {
-        let movetime = args.clone().nth(i + 1)?;
-        opts.movetime = Some(movetime.parse().ok()?);
-    } else if let Some(i) = args.clone().position(|s| s == "depth") {
-        let depth = args.clone().nth(i + 1)?;
-        opts.depth = Some(depth.parse().ok()?);
+    game.searcher.begin_search(opts, &game.board, &game.zobrist);
+    Some(())
+}
+
+pub fn setoption(game: &mut Game, cmd: &str) -> Option<()> {
+    let args: Vec<&str> = cmd.split_whitespace().collect();
+    let name_idx = args.iter().position(|s| *s == "name")?;
+    let value_idx = args.iter().position(|s| *s == "value");
+    let name = args[name_idx + 1..value_idx.unwrap_or(args.len())].join(" ");
+    let value = value_idx.map(|i| args[i + 1..].join(" "));
+
+    match name.to_lowercase().as_str() {
+        "threads" => {
+            game.search_opts.threads = value?.parse::<usize>().ok()?.clamp(1, MAX_THREADS);
+        },
+        "hash" => {
+            game.searcher.resize_transposition_table(value?.parse::<usize>().ok()?.clamp(1, TranspositionTable::MAX_SIZE_MB));
+        },
+        "clear hash" => {
+            game.searcher.clear_transposition_table();
+        },
+        "multipv" => {
+            game.search_opts.multipv = value?.parse::<usize>().ok()?.clamp(1, MAX_MULTIPV);
+        },
+        "contempt" => {
+            game.search_opts.contempt = value?.parse::<i32>().ok()?.clamp(-MAX_CONTEMPT, MAX_CONTEMPT);
+        },
+        "mate checks only" => {
+            game.search_opts.mate_checks_only = value?.parse::<bool>().ok()?;
+        },
+        // Pondering is controlled by the GUI through go ponder, so there is nothing to set
+        "ponder" => (),
+        _ => {
+            println!("No such option: {}", name);
+        },
     }
 
-    game.searcher.begin_search(opts, &mut game.board, &game.zobrist, &mut game.movegen);
     Some(())
 }
 
+fn print_info(info: SearchInfo) {
+    match info {
+        SearchInfo::Iteration(diag) => {
+            let score = if diag.is_mate_score() {
+                format!("mate {}", diag.mate_in())
+            } else {
+                format!("cp {}", diag.centipawns())
+            };
+
+            let mut line = format!(
+                "info depth {} seldepth {} multipv {} score {} nodes {} nps {} time {} hashfull {}",
+                diag.depth_searched,
+                diag.seldepth,
+                diag.multipv,
+                score,
+                diag.nodes,
+                diag.nps(),
+                diag.time.as_millis(),
+                diag.hashfull,
+            );
+
+            let pv: Vec<String> = diag.pv.moves().iter().filter_map(|&m| name_from_move(m)).collect();
+            if !pv.is_empty() {
+                line.push_str(&format!(" pv {}", pv.join(" ")));
+            }
+
+            println!("{}", line);
+        },
+        SearchInfo::CurrentMove { depth, m, number } => {
+            if let Some(name) = name_from_move(m) {
+                println!("info depth {} currmove {} currmovenumber {}", depth, name, number);
+            }
+        },
+        SearchInfo::NoMate { moves, checks_only } => {
+            let checks = if checks_only { " with checks only" } else { "" };
+            println!("info string no mate in {}{}", moves, checks);
+        },
+        SearchInfo::MateStopped { moves } => {
+            println!("info string mate search stopped without a result at mate in {}", moves);
+        },
+    }
+}
+
+/// Parses the value following `name` in `cmd`. Returns `Some(None)` if `name` isn't present and
+/// `None` if its value is missing or malformed.
+fn parse_arg<T: FromStr>(cmd: &str, name: &str) -> Option<Option<T>> {
+    let mut args = cmd.split_whitespace();
+
+    if args.by_ref().any(|s| s == name) {
+        Some(Some(args.next()?.parse().ok()?))
+    } else {
+        Some(None)
+    }
+}
+
+/// Parses the moves following `searchmoves` in `cmd`, up to the first token that isn't a move.
+fn parse_searchmoves(board: &Board, cmd: &str) -> Option<Vec<Move>> {
+    let mut args = cmd.split_whitespace();
+    args.by_ref().find(|s| *s == "searchmoves")?;
+
+    Some(args.map_while(|s| move_from_name(board, s)).collect())
+}
+
 pub fn display(game: &Game) {
     let mut s = String::new();
     s.push('\n');
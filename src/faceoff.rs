@@ -6,14 +6,11 @@ use termion::{async_stdin, clear, color, cursor, event::Key, input::TermRead, ra
 
 use crate::{tui::display_board, CommandDisplayMethod};
 
-pub async fn start(mut opponent: ExternalUci, positions: PathBuf, movetime: u32, display: CommandDisplayMethod) -> Result<(), String> {
+pub async fn start(mut opponent: ExternalUci, positions: PathBuf, opts: SearchOptions, display: CommandDisplayMethod) -> Result<(), String> {
     let fens = fs::read_to_string(positions.clone()).map_err(|_| format!("failed to read {}", positions.display()))?;
     let fens = fens.lines();
 
-    let opts = SearchOptions {
-        movetime: Some(movetime),
-        depth: None,
-    };
+    let movetime = opts.movetime.unwrap_or_default();
     let (mut wins, mut losses, mut draws) = (0, 0, 0);
     let mut opponent_in_search = false;
 
@@ -32,7 +29,7 @@ pub async fn start(mut opponent: ExternalUci, positions: PathBuf, movetime: u32,
         for i in 0..=1 {
             let white = if i == 0 { PlayerType::Computer } else { PlayerType::Human };
             let black = if i == 0 { PlayerType::Human } else { PlayerType::Computer };
-            let mut game = Game::new(Some(fen.to_string()), opts, white, black);
+            let mut game = Game::new(Some(fen.to_string()), opts.clone(), white, black);
             let mut result = GameResult::InProgress;
 
             if let Some(sout) = stdout.as_mut() {
@@ -221,7 +218,7 @@ pub fn move_from_name(board: &Board, m: &str) -> Option<Move> {
     Some(Move::from_start_end_flagged(start_sqr.square(), target_sqr.square(), flag))
 }
 
-fn name_from_move(m: Move) -> Option<String> {
+pub fn name_from_move(m: Move) -> Option<String> {
     let start_sqr = name_from_square(m.start())?;
     let target_sqr = name_from_square(m.target())?;
     let mut name = format!("{}{}", start_sqr, target_sqr);
@@ -1,14 +1,15 @@
 use std::{ffi::OsString, path::PathBuf};
 
-use engine::{board::{piece::Piece, zobrist::Zobrist, Board}, color::{Black, White}, eval::Evaluation, game::PlayerType, move_gen::magics, precomp, search::{options::SearchOptions, see::static_exchange_eval}};
+use engine::{board::{piece::Piece, zobrist::Zobrist, Board}, color::{Black, White}, eval::Evaluation, game::PlayerType, move_gen::magics, precomp, move_gen::move_generator::MoveGenerator, search::{diagnostics::SearchInfo, handle::SearchHandle, options::SearchOptions, see::static_exchange_eval, trace::{self as search_trace, TraceFormat, TraceOptions}}};
 use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum};
 use engine::game::Game;
 use external_uci::ExternalUci;
-use faceoff::move_from_name;
+use faceoff::{move_from_name, name_from_move};
 
 mod perft;
 mod tui;
 mod faceoff;
+mod trace;
 
 
 #[derive(Parser)]
@@ -30,6 +31,39 @@ enum Commands {
         #[arg(long, short, value_name = "DEPTH", default_value = "4")]
         depth: u16,
 
+        /// Number of best moves to show search evaluations and principal variations for.
+        #[arg(long, value_name = "LINES", default_value = "1")]
+        multipv: usize,
+
+        /// Only search these moves.
+        #[arg(long, value_name = "MOVE", num_args = 1..)]
+        searchmoves: Option<Vec<String>>,
+
+        /// Don't search these moves.
+        #[arg(long, value_name = "MOVE", num_args = 1..)]
+        exclude: Vec<String>,
+
+        /// Look for a forced mate in at most this many moves instead of searching to a depth.
+        #[arg(long, value_name = "MOVES")]
+        mate: Option<u16>,
+
+        /// Only try checking moves for the mating side when looking for a forced mate.
+        #[arg(long, requires = "mate")]
+        checks_only: bool,
+
+        /// Write the explored search tree to this file, as DOT if it ends in `.dot` or `.gv` and
+        /// as JSON otherwise. JSON dumps can be viewed with the `trace` command.
+        #[arg(long, value_name = "FILE")]
+        trace: Option<PathBuf>,
+
+        /// Leave nodes further than this many plies from the root out of the search tree.
+        #[arg(long, value_name = "PLIES", default_value = "4", requires = "trace")]
+        trace_depth: u8,
+
+        /// Stop recording the search tree after this many nodes.
+        #[arg(long, value_name = "NODES", default_value = "100000", requires = "trace")]
+        trace_nodes: usize,
+
         /// Evaluate the static exchange evaluation of a given move. Overrides all other evaluation
         /// arguments.
         #[arg(long, value_name = "MOVE")]
@@ -134,6 +168,10 @@ enum Commands {
         /// Run the TUI in debug mode.
         #[arg(long, short)]
         debug: bool,
+
+        /// Let the computer think on the human's time.
+        #[arg(long)]
+        ponder: bool,
     },
     /// Faceoff against a different chess engine that implements UCI.
     Faceoff {
@@ -155,6 +193,65 @@ enum Commands {
         /// The method with which to display the games as they are played.
         #[arg(long, short, value_name = "DISPLAY_METHOD", default_value = "none")]
         display: CommandDisplayMethod,
+
+        /// Disable null move pruning.
+        #[arg(long)]
+        no_null_move: bool,
+
+        /// Disable reverse futility pruning.
+        #[arg(long)]
+        no_reverse_futility: bool,
+
+        /// Disable razoring.
+        #[arg(long)]
+        no_razoring: bool,
+
+        /// Disable futility pruning.
+        #[arg(long)]
+        no_futility: bool,
+
+        /// Disable move count pruning.
+        #[arg(long)]
+        no_move_count_pruning: bool,
+
+        /// Disable delta pruning in quiescence search.
+        #[arg(long)]
+        no_delta_pruning: bool,
+
+        /// Disable pruning of losing captures in quiescence search.
+        #[arg(long)]
+        no_see_pruning: bool,
+
+        /// How many centipawns worse than equal a draw is for chess-a-tron. Use a negative value
+        /// to make it seek draws against a stronger opponent.
+        #[arg(long, default_value = "0", allow_negative_numbers = true)]
+        contempt: i32,
+    },
+    /// View a search tree written by `eval --trace`.
+    Trace {
+        /// The JSON file the search tree was written to.
+        file: PathBuf,
+
+        /// Only show nodes up to this many plies from the root.
+        #[arg(long, value_name = "PLIES")]
+        max_ply: Option<u8>,
+
+        /// Only show what was searched after these moves from the root.
+        #[arg(long, value_name = "MOVE", num_args = 1..)]
+        line: Vec<String>,
+
+        /// Only show nodes pruned for this reason (e.g. "null move", "futility" or "tt cutoff"),
+        /// and the nodes leading to them.
+        #[arg(long, value_name = "REASON")]
+        pruning: Option<String>,
+
+        /// Only show nodes that had a transposition table entry, and the nodes leading to them.
+        #[arg(long)]
+        tt_hits: bool,
+
+        /// Only show the last search of the root, which is the deepest.
+        #[arg(long)]
+        last: bool,
     },
     /// Launch the UCI.
     Uci,
@@ -205,7 +302,15 @@ async fn main() {
         Commands::Eval {
             fen,
             see,
-            depth:_,
+            depth,
+            multipv,
+            searchmoves,
+            exclude,
+            mate,
+            checks_only,
+            trace,
+            trace_depth,
+            trace_nodes,
             material,
             psqt,
             imbalance,
@@ -240,6 +345,70 @@ async fn main() {
                 println!("mg evaluation: {}", eval.middle_game_eval::<White, Black>());
                 println!("eg evaluation: {}", eval.end_game_eval::<White, Black>());
 
+                if !MoveGenerator::default().generate_moves(&board, false).is_empty() {
+                    let mut opts = SearchOptions {
+                        movetime: None,
+                        depth: Some(depth),
+                        multipv,
+                        mate,
+                        mate_checks_only: checks_only,
+                        trace: trace.map(|path| TraceOptions {
+                            format: TraceFormat::from_path(&path),
+                            path,
+                            max_ply: trace_depth,
+                            max_nodes: trace_nodes,
+                        }),
+                        ..Default::default()
+                    };
+                    for name in searchmoves.iter().flatten() {
+                        let Some(m) = move_from_name(&board, name) else {
+                            throw!(InvalidValue; "{} is not a valid move", name);
+                        };
+                        opts.search_moves.get_or_insert_with(Vec::new).push(m);
+                    }
+                    for name in exclude {
+                        let Some(m) = move_from_name(&board, &name) else {
+                            throw!(InvalidValue; "{} is not a valid move", name);
+                        };
+                        opts.excluded_moves.push(m);
+                    }
+
+                    let mut searcher = SearchHandle::new();
+                    searcher.begin_search(opts, &board, &zobrist);
+                    searcher.wait();
+
+                    // The last line reported for each MultiPV index is from the deepest iteration
+                    let mut lines = vec![None; multipv.max(1)];
+                    for info in searcher.poll_info() {
+                        match info {
+                            SearchInfo::Iteration(diag) => {
+                                if let Some(line) = lines.get_mut(diag.multipv.wrapping_sub(1)) {
+                                    *line = Some(diag);
+                                }
+                            },
+                            SearchInfo::NoMate { moves, checks_only } => {
+                                let checks = if checks_only { " with checks only" } else { "" };
+                                println!("no mate in {}{}", moves, checks);
+                            },
+                            SearchInfo::MateStopped { moves } => {
+                                println!("mate search stopped without a result at mate in {}", moves);
+                            },
+                            SearchInfo::CurrentMove { .. } => (),
+                        }
+                    }
+
+                    for (i, diag) in lines.into_iter().flatten().enumerate() {
+                        let pv: Vec<String> = diag.pv.moves().iter().filter_map(|&m| name_from_move(m)).collect();
+                        let prefix = if multipv > 1 { format!("line {}: ", i + 1) } else { String::new() };
+                        if diag.is_mate_score() {
+                            println!("{}search evaluation (depth {}, side to move): M{}", prefix, diag.depth_searched, diag.mate_in());
+                        } else {
+                            println!("{}search evaluation (depth {}, side to move): {}", prefix, diag.depth_searched, diag.evaluation);
+                        }
+                        println!("{}principal variation: {}", prefix, pv.join(" "));
+                    }
+                }
+
                 if material {
                     let w = (eval.piece_value_mg::<White, Black>(), eval.piece_value_eg::<White, Black>());
                     let b = (eval.piece_value_mg::<Black, White>(), eval.piece_value_eg::<Black, White>());
@@ -376,8 +545,9 @@ async fn main() {
             white_player,
             black_player,
             debug,
+            ponder,
         } => {
-            tui::start(fen, white_player.into(), black_player.into(), !no_truecolor, debug);
+            tui::start(fen, white_player.into(), black_player.into(), !no_truecolor, debug, ponder);
         },
         Commands::Faceoff {
             engine_uci: engine,
@@ -385,13 +555,34 @@ async fn main() {
             positions,
             movetime,
             display,
+            no_null_move,
+            no_reverse_futility,
+            no_razoring,
+            no_futility,
+            no_move_count_pruning,
+            no_delta_pruning,
+            no_see_pruning,
+            contempt,
         } => {
+            let opts = SearchOptions {
+                movetime: Some(movetime),
+                null_move: !no_null_move,
+                reverse_futility: !no_reverse_futility,
+                razoring: !no_razoring,
+                futility: !no_futility,
+                move_count_pruning: !no_move_count_pruning,
+                delta_pruning: !no_delta_pruning,
+                see_pruning: !no_see_pruning,
+                contempt,
+                ..Default::default()
+            };
+
             let mut cmd = std::process::Command::new(engine.clone()).args(args.clone()).spawn();
             let opponent = ExternalUci::new_with_args(engine.to_str().unwrap(), args).await.unwrap();
             match &mut cmd {
                 Ok(proc) => {
                     proc.kill().unwrap();
-                    match faceoff::start(opponent, positions, movetime, display).await {
+                    match faceoff::start(opponent, positions, opts, display).await {
                         Ok(()) => (),
                         Err(e) => {
                             throw!(Io; "{}", e);
@@ -403,6 +594,23 @@ async fn main() {
                 }
             };
         },
+        Commands::Trace {
+            file,
+            max_ply,
+            line,
+            pruning,
+            tt_hits,
+            last,
+        } => {
+            let nodes = match search_trace::read_json(&file) {
+                Ok(nodes) => nodes,
+                Err(e) => {
+                    throw!(Io; "failed to read {}: {}", file.display(), e);
+                },
+            };
+
+            trace::show(&nodes, &trace::TraceFilter { max_ply, line, pruning, tt_hits, last });
+        },
         Commands::Uci => {
             uci::start();
         }
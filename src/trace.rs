@@ -0,0 +1,88 @@
+use engine::search::{trace::TraceNode, transpositions::TranspositionNodeType};
+
+/// Which nodes of a search tree dump to show.
+pub struct TraceFilter {
+    /// Only show nodes up to this many plies from the root
+    pub max_ply: Option<u8>,
+    /// Only show the subtrees reached by these moves from the root
+    pub line: Vec<String>,
+    /// Only show nodes pruned for this reason, and the nodes leading to them
+    pub pruning: Option<String>,
+    /// Only show nodes with a transposition table entry, and the nodes leading to them
+    pub tt_hits: bool,
+    /// Only show the tree of the last root searched
+    pub last: bool,
+}
+
+/// Prints the nodes that pass the filter as an indented tree.
+pub fn show(nodes: &[TraceNode], filter: &TraceFilter) {
+    let mut children = vec![Vec::new(); nodes.len()];
+    let mut roots = Vec::new();
+    for node in nodes {
+        match node.parent {
+            Some(parent) => children[parent].push(node.id),
+            None => roots.push(node.id),
+        }
+    }
+    if filter.last {
+        roots = roots.last().copied().into_iter().collect();
+    }
+
+    // Parents are always recorded before their children, so going backwards visits every child
+    // before its parent
+    let mut keep = vec![false; nodes.len()];
+    for node in nodes.iter().rev() {
+        let matches = filter.pruning.as_ref().is_none_or(|p| node.pruning.as_ref() == Some(p))
+            && (!filter.tt_hits || node.tt_hit);
+        keep[node.id] = matches || children[node.id].iter().any(|&c| keep[c]);
+    }
+
+    // Follow the line from every root, as each iteration searches it again
+    let mut starts = roots;
+    for m in filter.line.iter() {
+        starts = starts.iter().flat_map(|&id| children[id].iter().copied().filter(|&c| nodes[c].m == *m)).collect();
+    }
+
+    let mut shown = 0;
+    for start in starts {
+        shown += print_subtree(nodes, &children, &keep, filter, start, 0);
+    }
+
+    if shown == 0 {
+        println!("no nodes match");
+    }
+}
+
+fn print_subtree(nodes: &[TraceNode], children: &[Vec<usize>], keep: &[bool], filter: &TraceFilter, id: usize, indent: usize) -> usize {
+    let node = &nodes[id];
+    if !keep[id] || filter.max_ply.is_some_and(|max| node.ply > max) {
+        return 0;
+    }
+
+    println!("{}{}", "  ".repeat(indent), describe(node));
+    1 + children[id].iter().map(|&c| print_subtree(nodes, children, keep, filter, c, indent + 1)).sum::<usize>()
+}
+
+fn describe(node: &TraceNode) -> String {
+    let mut s = format!("{} d{}", node.m, node.depth);
+
+    if node.score.is_some() || node.pruning.is_none() {
+        s.push_str(&format!(" [{}, {}]", node.alpha, node.beta));
+    }
+    if let Some(score) = node.score {
+        let bound = match node.bound {
+            Some(TranspositionNodeType::LowerBound) => " (lower)",
+            Some(TranspositionNodeType::UpperBound) => " (upper)",
+            _ => "",
+        };
+        s.push_str(&format!(" -> {}{}", score, bound));
+    }
+    if node.tt_hit {
+        s.push_str(" tt");
+    }
+    if let Some(reason) = &node.pruning {
+        s.push_str(&format!(" ({})", reason));
+    }
+
+    s
+}
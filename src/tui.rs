@@ -51,6 +51,10 @@ pub fn display_board(
                         write!(stdout, "    Evaluation: {}", diag.evaluation).unwrap();
                     }
                 }
+
+                if sqr.rank() == 2 && !diag.pv.is_empty() {
+                    write!(stdout, "    PV: {:?}", diag.pv).unwrap();
+                }
             }
 
             if sqr.rank() == if flip { 0 } else { 7 } {
@@ -207,11 +211,12 @@ enum InputMode {
 }
 
 
-pub fn start(fen: String, white: PlayerType, black: PlayerType, truecolor: bool, debug: bool) {
+pub fn start(fen: String, white: PlayerType, black: PlayerType, truecolor: bool, debug: bool, ponder: bool) {
     let mut stdin = async_stdin().keys();
     let mut stdout = stdout().into_raw_mode().unwrap();
 
     let mut game = Game::new(Some(fen), SearchOptions::default(), white, black);
+    game.ponder = ponder;
     let mut result = GameResult::InProgress;
     let mut cursor = (1, 1);
     let mut selected: Option<(i8, i8)> = None;
@@ -516,7 +521,7 @@ pub fn start(fen: String, white: PlayerType, black: PlayerType, truecolor: bool,
 
         let is_terminal = if game_over { true } else { result.is_terminal() };
 
-        while game.player_to_move == PlayerType::Computer && !is_terminal {
+        if game.player_to_move == PlayerType::Computer && !is_terminal {
             if let Some(res) = game.try_make_computer_move() {
                 result = res;
                 diagnostics = game.searcher.diagnostics;
@@ -524,7 +529,6 @@ pub fn start(fen: String, white: PlayerType, black: PlayerType, truecolor: bool,
                 display_board(&mut stdout, &game.board, cursor, selected, &valid_moves, overlayed_bitboard, truecolor, Some(diagnostics), false);
 
                 stdout.flush().unwrap();
-                break;
             }
         }
 